@@ -0,0 +1,246 @@
+use std::sync::{Arc, RwLock};
+
+use sim::{Bullet, DeathCause, Effect, Tank, World};
+
+#[derive(Debug, Clone)]
+pub enum EntityRef {
+    Tank(Arc<RwLock<Tank>>),
+    Bullet(Arc<RwLock<Bullet>>),
+}
+
+// Two entities that ran into each other; see World::detect_collisions.
+pub type Collision = (EntityRef, EntityRef);
+
+// Decides what happens to entities that collided during a step. Each colliding pair is reported
+// once, and a tank always comes before a bullet.
+pub trait CollisionResolver {
+    fn resolve(&self, world: &World, pairs: &[Collision]);
+}
+
+// Which CollisionResolver the world uses.
+#[derive(Clone)]
+pub enum CollisionRule {
+    Lethal,
+    Damage,
+    Bounce,
+    Custom(Arc<dyn CollisionResolver>),
+}
+
+impl CollisionRule {
+    pub fn resolver(&self) -> Arc<dyn CollisionResolver> {
+        match self {
+            CollisionRule::Lethal => Arc::new(LethalResolver),
+            CollisionRule::Damage => Arc::new(DamageResolver),
+            CollisionRule::Bounce => Arc::new(BounceResolver),
+            CollisionRule::Custom(r) => Arc::clone(r),
+        }
+    }
+}
+
+impl core::fmt::Debug for CollisionRule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CollisionRule::Lethal => write!(f, "Lethal"),
+            CollisionRule::Damage => write!(f, "Damage"),
+            CollisionRule::Bounce => write!(f, "Bounce"),
+            CollisionRule::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+fn kill(world: &World, r: &EntityRef) {
+    match r {
+        EntityRef::Tank(t) => {
+            let mut t = t.write().unwrap();
+            if t.protection == 0 {
+                t.kill(DeathCause::Collision);
+            }
+        }
+        EntityRef::Bullet(b) => {
+            let mut b = b.write().unwrap();
+            if !b.dead {
+                b.dead = true;
+                world.add_effect(Effect::Impact { pos: b.pos });
+            }
+        }
+    }
+}
+
+// Everything involved in a collision dies.
+pub struct LethalResolver;
+
+impl CollisionResolver for LethalResolver {
+    fn resolve(&self, world: &World, pairs: &[Collision]) {
+        for (a, b) in pairs {
+            kill(world, a);
+            kill(world, b);
+        }
+    }
+}
+
+// Tanks lose health rather than dying outright: `bullet_damage` (or `heavy_damage`) for each bullet
+// that hits them, and `ram_damage` each when two tanks run into each other. Bullets are used up
+// either way.
+pub struct DamageResolver;
+
+impl CollisionResolver for DamageResolver {
+    fn resolve(&self, world: &World, pairs: &[Collision]) {
+        fn hurt(r: &EntityRef, by: &EntityRef, world: &World) {
+            let damage = match by {
+                EntityRef::Tank(_) => world.config.ram_damage,
+                EntityRef::Bullet(b) => world.config.bullet_damage_of(b.read().unwrap().kind),
+            };
+            match r {
+                EntityRef::Tank(t) => {
+                    let mut t = t.write().unwrap();
+                    if t.protection > 0 {
+                        return;
+                    }
+                    t.health = t.health.saturating_sub(damage);
+                    if t.health <= 0 {
+                        t.kill(DeathCause::Collision);
+                    }
+                }
+                EntityRef::Bullet(_) => kill(world, r),
+            }
+        }
+        for (a, b) in pairs {
+            hurt(a, b, world);
+            hurt(b, a, world);
+        }
+    }
+}
+
+// Nobody gets hurt: tanks go back to where they started the step, and bullets glance off them.
+pub struct BounceResolver;
+
+impl CollisionResolver for BounceResolver {
+    fn resolve(&self, _world: &World, pairs: &[Collision]) {
+        for (a, b) in pairs {
+            match (a, b) {
+                (EntityRef::Tank(t), EntityRef::Bullet(b)) | (EntityRef::Bullet(b), EntityRef::Tank(t)) => {
+                    let center = t.read().unwrap().pos;
+                    let mut b = b.write().unwrap();
+                    let normal = b.pos + (-center);
+                    let len = normal.mag();
+                    b.vel = if len > 0.0 {
+                        let normal = normal * (1.0 / len);
+                        b.vel + normal * (-2.0 * b.vel.dot(&normal))
+                    } else {
+                        -b.vel
+                    };
+                    b.pos = b.prev_pos;
+                }
+                (EntityRef::Tank(t), EntityRef::Tank(o)) => {
+                    for t in &[t, o] {
+                        let mut t = t.write().unwrap();
+                        t.pos = t.prev_pos;
+                    }
+                }
+                (EntityRef::Bullet(_), EntityRef::Bullet(_)) => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use sim::{Configuration, TankState};
+    use space::Pair;
+
+    // Two tanks close enough to run into each other, and a bullet sitting on the first.
+    fn crash(collision: CollisionRule) -> World {
+        let mut world = Configuration {
+            collision,
+            ..Configuration::default()
+        }
+        .build();
+        for x in &[0.0, 8.0] {
+            let mut tank = Tank::dummy(Pair { x: *x, y: 0.0 }, 0);
+            tank.health = 100;
+            world.add_tank(tank).unwrap();
+        }
+        let mut shot = Bullet::new(Pair { x: -4.0, y: 3.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        world
+    }
+
+    fn tank(world: &World, i: usize) -> (i32, bool) {
+        let tanks = world.tanks.read().unwrap();
+        let t = tanks[i].read().unwrap();
+        (t.health, t.state == TankState::Dead)
+    }
+
+    fn bullet_dead(world: &World) -> bool {
+        let dead = world.bullets.read().unwrap().is_empty();
+        dead
+    }
+
+    #[test]
+    fn lethal_collisions_kill_everything_involved() {
+        let mut world = crash(CollisionRule::Lethal);
+        world.step();
+        assert!(tank(&world, 0).1 && tank(&world, 1).1);
+        assert!(bullet_dead(&world));
+        assert_eq!(world.effects().len(), 1);
+    }
+
+    #[test]
+    fn damage_takes_health_per_hit() {
+        let mut world = crash(CollisionRule::Damage);
+        world.step();
+        assert_eq!(tank(&world, 0), (100 - 50 - 25, false));
+        assert_eq!(tank(&world, 1), (100 - 50, false));
+        assert!(bullet_dead(&world));
+    }
+
+    #[test]
+    fn bounced_bullets_glance_off() {
+        let mut world = Configuration {
+            collision: CollisionRule::Bounce,
+            ..Configuration::default()
+        }
+        .build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let mut shot = Bullet::new(Pair { x: -9.0, y: 0.0 }, Pair { x: 4.0, y: 0.0 });
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        world.step();
+        assert!(!tank(&world, 0).1);
+        let bullets = world.bullets.read().unwrap();
+        let b = bullets[0].read().unwrap();
+        assert_eq!(b.pos, Pair { x: -9.0, y: 0.0 });
+        assert_eq!(b.vel, Pair { x: -4.0, y: 0.0 });
+    }
+
+    struct Recorder(Mutex<Vec<(bool, bool)>>);
+
+    impl CollisionResolver for Recorder {
+        fn resolve(&self, _world: &World, pairs: &[Collision]) {
+            let is_tank = |r: &EntityRef| match r {
+                EntityRef::Tank(_) => true,
+                EntityRef::Bullet(_) => false,
+            };
+            let mut seen = self.0.lock().unwrap();
+            seen.extend(pairs.iter().map(|(a, b)| (is_tank(a), is_tank(b))));
+        }
+    }
+
+    #[test]
+    fn custom_rules_decide_what_collisions_do() {
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let mut world = crash(CollisionRule::Custom(recorder.clone()));
+        world.step();
+        // Each pair once, tanks first; nothing is hurt unless the rule says so
+        let mut seen = recorder.0.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, vec![(true, false), (true, true)]);
+        assert_eq!(tank(&world, 0), (100, false));
+        assert_eq!(tank(&world, 1), (100, false));
+        assert!(!bullet_dead(&world));
+    }
+}
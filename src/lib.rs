@@ -5,6 +5,7 @@
 extern crate num_derive;
 extern crate num_traits;
 extern crate serde;
+extern crate serde_json;
 extern crate wasmi;
 extern crate websocket;
 extern crate native_tls;
@@ -13,4 +14,9 @@ extern crate bus;
 pub mod sim;
 pub mod space;
 pub mod vm;
+pub mod rng;
 pub mod server;
+pub mod timestep;
+pub mod snapshot;
+pub mod collision;
+pub mod replay;
@@ -6,44 +6,337 @@ extern crate websocket;
 
 extern crate RANKS;
 
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{env, fs};
 
 use serde::Serialize;
 
 use websocket::OwnedMessage;
 
-use RANKS::sim::{Bullet, Configuration, Identity, Tank, Team};
-use RANKS::space::Pair;
-use RANKS::server::{TankServer, ClientMessage};
+use RANKS::sim::{Bullet, Configuration, Effect, Entity, Identity, Tank, TankBuilder, Team, TeamMeta, TeamStart, World, Zone};
+use RANKS::space::{Pair, AABB};
+use RANKS::server::{TankServer, ClientMessage, ControlCommand, Hello, StatusPacket, PROTOCOL_VERSION};
+use RANKS::timestep::{FixedTimestep, Playback};
 
-const WORLD_SIZE: usize = 500;
+const DEFAULT_TPS: f64 = 1000.0;
 
-const DELAY_DURATION: Duration = Duration::from_millis(1);
+const DEFAULT_BENCH_STEPS: usize = 1000;
+
+// The smallest useful tank: exports `memory` and a `tank` that spins forever. Used to populate
+// the sample world for `schema`.
+const SAMPLE_PROGRAM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // types: () -> ()
+    0x03, 0x02, 0x01, 0x00, // functions: tank
+    0x05, 0x03, 0x01, 0x00, 0x01, // memory: 1 page
+    0x07, 0x11, 0x02, // exports:
+    0x04, 0x74, 0x61, 0x6e, 0x6b, 0x00, 0x00, // "tank"
+    0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, // "memory"
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b, // code: loop { br 0 }
+];
+
+const TEAM_COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#bfef45",
+];
+
+#[derive(Serialize)]
+struct StartupPacket<'a> {
+    teams: &'a HashMap<Team, TeamMeta>,
+    arena: AABB,
+    obstacles: &'a Vec<AABB>,
+    zones: &'a Vec<Zone>,
+    tank_radius: f32,
+    bullet_radius: f32,
+    heavy_bullet_radius: f32,
+}
 
 #[derive(Serialize)]
 struct UpdatePacket<'a> {
+    teams: &'a HashMap<Team, TeamMeta>,
     tanks: &'a Vec<Identity<Arc<RwLock<Tank>>>>,
     bullets: &'a Vec<Identity<Arc<RwLock<Bullet>>>>,
+    effects: &'a Vec<Effect>,
+}
+
+// What gets printed for each step.
+#[derive(Clone, Copy, PartialEq)]
+enum Verbosity {
+    Quiet,
+    Steps, // Just the step number
+    Full, // The step number and the update packet
+}
+
+impl Verbosity {
+    fn parse(s: &str) -> Option<Verbosity> {
+        match s {
+            "quiet" => Some(Verbosity::Quiet),
+            "steps" => Some(Verbosity::Steps),
+            "full" => Some(Verbosity::Full),
+            _ => None,
+        }
+    }
+}
+
+// The update packet is only built when it's going to be printed.
+fn log_step<F: FnOnce() -> String>(verbosity: Verbosity, stepnum: usize, json: F) {
+    if verbosity == Verbosity::Quiet {
+        return;
+    }
+    println!("Step: {}", stepnum);
+    if verbosity == Verbosity::Full {
+        println!("json: {}", json());
+    }
+}
+
+fn startup_packet(world: &World) -> StartupPacket {
+    StartupPacket {
+        teams: &world.config.teams,
+        arena: world.arena_bounds(),
+        obstacles: &world.config.obstacles,
+        zones: &world.config.zones,
+        tank_radius: world.config.tank_radius,
+        bullet_radius: world.config.bullet_radius,
+        heavy_bullet_radius: world.config.heavy_radius,
+    }
+}
+
+// The world `schema` describes its packets with: one team, one tank, one bullet in flight.
+fn schema_world() -> World {
+    let mut config = Configuration::default();
+    config.teams.insert(0, TeamMeta {
+        name: "sample".into(),
+        color: TEAM_COLORS[0].into(),
+    });
+    let mut world = config.build();
+    let tank = TankBuilder::new(Pair { x: 100.0, y: 0.0 }, 0, SAMPLE_PROGRAM.to_vec(), world.config.clone())
+        .with_aim(::std::f32::consts::PI)
+        .build()
+        .expect("Sample program failed to instantiate");
+    world.add_tank(tank).expect("Sample tank is out of bounds");
+    let mut bullet = Bullet::new(Pair { x: 70.0, y: 0.0 }, Pair { x: -world.config.bullet_v, y: 0.0 });
+    bullet.step(&world);
+    world.add_bullet(bullet);
+    world
+}
+
+fn update_json(world: &World) -> String {
+    serde_json::to_string(&UpdatePacket {
+        teams: &world.config.teams,
+        tanks: &*world.tanks.read().unwrap(),
+        bullets: &*world.bullets.read().unwrap(),
+        effects: &*world.effects(),
+    })
+    .unwrap()
+}
+
+// Which way tanks on the spawn ring start out facing, both heading and aim.
+#[derive(Clone, Copy, PartialEq)]
+enum Facing {
+    East, // Angle 0, wherever they are
+    Center, // Toward the middle of the arena, so opposite sides face each other
+}
+
+impl Facing {
+    fn parse(s: &str) -> Option<Facing> {
+        match s {
+            "east" => Some(Facing::East),
+            "center" => Some(Facing::Center),
+            _ => None,
+        }
+    }
 }
 
 enum Mode {
     LocalHeadless,
     WebsocketWatch,
+    Schema,
+    Bench,
+}
+
+struct Options {
+    tps: f64,
+    copies: usize, // Tanks spawned per program, all on that program's team
+    steps: usize,
+    verbosity: Option<Verbosity>, // Each mode has its own default
+    facing: Option<Facing>, // When not given, teams with a --start keep its angle
+    names: Vec<String>,
+    progs: Vec<Vec<u8>>,
+    starts: HashMap<Team, TeamStart>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = OsString>>(mut args: I) -> Options {
+        let mut opts = Options {
+            tps: DEFAULT_TPS,
+            copies: 1,
+            steps: DEFAULT_BENCH_STEPS,
+            verbosity: None,
+            facing: None,
+            names: Vec::new(),
+            progs: Vec::new(),
+            starts: HashMap::new(),
+        };
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("--tps") => {
+                    opts.tps = args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| v.parse().ok())
+                        .filter(|&tps: &f64| tps > 0.0)
+                        .expect("--tps needs a positive number of ticks per second");
+                }
+                Some("--copies") => {
+                    opts.copies = args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| v.parse().ok())
+                        .filter(|&n: &usize| n > 0)
+                        .expect("--copies needs a positive number of tanks");
+                }
+                Some("--start") => {
+                    // TEAM=X,Y,ANGLE,TEMP,HP, where TEAM is the program's position on the command
+                    // line
+                    let spec = args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| parse_start(&v))
+                        .expect("--start needs TEAM=X,Y,ANGLE,TEMP,HP");
+                    opts.starts.insert(spec.0, spec.1);
+                }
+                Some("--verbosity") => {
+                    opts.verbosity = Some(args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| Verbosity::parse(&v))
+                        .expect("--verbosity needs one of quiet, steps or full"));
+                }
+                Some("--facing") => {
+                    opts.facing = Some(args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| Facing::parse(&v))
+                        .expect("--facing needs one of east or center"));
+                }
+                Some("--steps") => {
+                    opts.steps = args
+                        .next()
+                        .and_then(|v| v.into_string().ok())
+                        .and_then(|v| v.parse().ok())
+                        .expect("--steps needs a number of steps");
+                }
+                _ => {
+                    opts.progs
+                        .push(fs::read(&arg).expect(&format!("Couldn't read file {:#?}", arg)));
+                    opts.names.push(
+                        Path::new(&arg)
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+        }
+        opts
+    }
+
+    fn build_world(&self) -> World {
+        let progcount = self.progs.len() * self.copies;
+        let mut config = Configuration::default();
+        config.team_starts = self.starts.clone();
+        for (idx, name) in self.names.iter().enumerate() {
+            config.teams.insert(idx as Team, TeamMeta {
+                name: name.clone(),
+                color: TEAM_COLORS[idx % TEAM_COLORS.len()].into(),
+            });
+        }
+        let mut world = config.build();
+        let config = world.config.clone();
+        // Spawn on a ring three quarters of the way out to the nearest edge of the world
+        let bound = &config.world_bound;
+        let center = bound.org + bound.dim * 0.5;
+        let radius = 0.75 * 0.5 * bound.dim.x.min(bound.dim.y);
+        let progs = self.progs.iter().enumerate().flat_map(|p| ::std::iter::repeat(p).take(self.copies));
+        for (idx, (team, prog)) in progs.enumerate() {
+            let pos = center + Pair::polar((idx as f32) / (progcount as f32) * 2.0 * ::std::f32::consts::PI) * radius;
+            let start = config.team_starts.get(&(team as Team));
+            // TankBuilder::new moves the tank to its team's start, if it has one
+            let spawn = start.map_or(pos, |s| s.spawn);
+            let facing = match (self.facing, start) {
+                (Some(facing), _) => Some(facing),
+                (None, None) => Some(Facing::East),
+                (None, Some(_)) => None,
+            };
+            let mut builder = TankBuilder::new(pos, team as Team, prog.clone(), config.clone());
+            if let Some(facing) = facing {
+                let angle = match facing {
+                    Facing::East => 0.0,
+                    Facing::Center => (center + (-spawn)).ang(),
+                };
+                builder = builder.with_angle(angle).with_aim(angle);
+            }
+            let tank = builder.build();
+            if let Ok(tank) = tank {
+                if let Err(e) = world.add_tank(tank) {
+                    println!("Couldn't add tank for team {}: {}", team, e);
+                }
+            }
+        }
+        world
+    }
+}
+
+// Step `world` up to `steps` times, stopping early if the match ends, and time each step.
+fn time_steps(world: &mut World, steps: usize) -> Vec<Duration> {
+    let mut times = Vec::with_capacity(steps);
+    for _ in 0..steps {
+        if world.finished() {
+            break;
+        }
+        let before = Instant::now();
+        world.step();
+        times.push(before.elapsed());
+    }
+    times
+}
+
+fn parse_start(spec: &str) -> Option<(Team, TeamStart)> {
+    let mut halves = spec.splitn(2, '=');
+    let team = halves.next()?.parse().ok()?;
+    let fields: Vec<&str> = halves.next()?.split(',').collect();
+    if fields.len() != 5 {
+        return None;
+    }
+    Some((team, TeamStart {
+        spawn: Pair {
+            x: fields[0].parse().ok()?,
+            y: fields[1].parse().ok()?,
+        },
+        angle: fields[2].parse().ok()?,
+        temp: fields[3].parse().ok()?,
+        hp: fields[4].parse().ok()?,
+    }))
 }
 
 fn main() {
     fn print_subcommands() {
         println!("Valid subcommands are:");
-        println!("local_headless");
-        println!("websocket_watch");
+        println!("local_headless [--tps N] [--verbosity quiet|steps|full] [--facing east|center] [--start TEAM=X,Y,ANGLE,TEMP,HP]... PROGRAM...");
+        println!("websocket_watch [--tps N] [--verbosity quiet|steps|full] [--facing east|center] [--start TEAM=X,Y,ANGLE,TEMP,HP]... PROGRAM...");
+        println!("schema");
+        println!("bench [--copies N] [--steps N] PROGRAM...");
     }
     let mode = match env::args_os().nth(1).map(|s| s.into_string()) {
         Some(Ok(s)) => match &s.as_str() {
             &"local_headless" => Mode::LocalHeadless,
             &"websocket_watch" => Mode::WebsocketWatch,
+            &"schema" => Mode::Schema,
+            &"bench" => Mode::Bench,
             _ => {
                 print_subcommands();
                 return;
@@ -54,107 +347,365 @@ fn main() {
             return;
         }
     };
+    let opts = Options::parse(env::args_os().skip(2));
+    let mut clock = FixedTimestep::from_tps(opts.tps);
     match mode {
-        Mode::LocalHeadless => {
-            let progs: Vec<Vec<u8>> = env::args_os()
-                .skip(2)
-                .map(|fname| fs::read(&fname).expect(&format!("Couldn't read file {:#?}", fname)))
-                .collect();
-            let progcount = progs.len();
-
-            let mut world = Configuration::default().build();
-            let config = world.config.clone();
-            for (idx, prog) in progs.into_iter().enumerate() {
-                let tank = Tank::new(
-                    Pair::polar((idx as f32) / (progcount as f32) * 2.0 * ::std::f32::consts::PI)
-                    * 0.75
-                    * (WORLD_SIZE as f32),
-                    idx as Team,
-                    prog,
-                    config.clone(),
-                    );
-                if let Ok(tank) = tank {
-                    world.add_tank(tank);
-                }
+        Mode::Schema => {
+            // Print one of each packet with representative values, as a reference for clients
+            let world = schema_world();
+            println!(
+                "hello: {}",
+                serde_json::to_string_pretty(&Hello {
+                    protocol: PROTOCOL_VERSION,
+                })
+                .unwrap()
+                );
+            println!(
+                "startup: {}",
+                serde_json::to_string_pretty(&startup_packet(&world)).unwrap()
+                );
+            println!(
+                "update: {}",
+                serde_json::to_string_pretty(&UpdatePacket {
+                    teams: &world.config.teams,
+                    tanks: &*world.tanks.read().unwrap(),
+                    bullets: &*world.bullets.read().unwrap(),
+                    effects: &*world.effects(),
+                })
+                .unwrap()
+                );
+            println!(
+                "status: {}",
+                serde_json::to_string_pretty(&StatusPacket {
+                    status: world.status(),
+                })
+                .unwrap()
+                );
+        }
+        Mode::Bench => {
+            // Step as fast as possible, with no output in the loop, and report the timings
+            let mut world = opts.build_world();
+            let start = Instant::now();
+            let mut times = time_steps(&mut world, opts.steps);
+            let total = start.elapsed();
+            if times.is_empty() {
+                println!("No steps were run");
+                return;
             }
+            let count = times.len();
+            times.sort();
+            println!("steps: {}", count);
+            println!("steps/second: {:.1}", count as f64 / total.as_secs_f64());
+            println!("average step: {:?}", total / count as u32);
+            println!("p99 step: {:?}", times[(count * 99 / 100).min(count - 1)]);
+        }
+        Mode::LocalHeadless => {
+            let mut world = opts.build_world();
+            let verbosity = opts.verbosity.unwrap_or(Verbosity::Full);
 
             let mut stepnum = 0;
+            let mut last = Instant::now();
             while !world.finished() {
-                world.step();
-                println!("Step: {}", stepnum);
-                println!(
-                    "json: {}",
-                    serde_json::to_string(&UpdatePacket {
-                        tanks: &*world.tanks.read().unwrap(),
-                        bullets: &*world.bullets.read().unwrap(),
-                    })
-                    .unwrap()
-                    );
-                sleep(DELAY_DURATION);
-                stepnum += 1;
-                //eprintln!("---\n{:?}", world);
+                let now = Instant::now();
+                let ticks = clock.advance(now - last);
+                last = now;
+                for _ in 0..ticks {
+                    if world.finished() {
+                        break;
+                    }
+                    world.step();
+                    log_step(verbosity, stepnum, || update_json(&world));
+                    stepnum += 1;
+                    //eprintln!("---\n{:?}", world);
+                }
+                sleep(clock.until_next());
             }
         }
         Mode::WebsocketWatch => {
-            let progs: Vec<Vec<u8>> = env::args_os()
-                .skip(2)
-                .map(|fname| fs::read(&fname).expect(&format!("Couldn't read file {:#?}", fname)))
-                .collect();
-            let progcount = progs.len();
-
-            let mut world = Configuration::default().build();
-            let config = world.config.clone();
-            for (idx, prog) in progs.into_iter().enumerate() {
-                let tank = Tank::new(
-                    Pair::polar((idx as f32) / (progcount as f32) * 2.0 * ::std::f32::consts::PI)
-                    * 0.75
-                    * (WORLD_SIZE as f32),
-                    idx as Team,
-                    prog,
-                    config.clone(),
-                    );
-                if let Ok(tank) = tank {
-                    world.add_tank(tank);
-                }
-            }
+            let mut world = opts.build_world();
+            // Clients get every update regardless; this is only what goes to stdout
+            let verbosity = opts.verbosity.unwrap_or(Verbosity::Steps);
 
-            let mut server = TankServer::new(Arc::new(OwnedMessage::Text("{}".into()))).unwrap();
+            let startup = serde_json::to_string(&startup_packet(&world)).unwrap();
+            let mut server = TankServer::new(Arc::new(OwnedMessage::Text(startup)), world.status()).unwrap();
             let rx = server.receiver().unwrap();
             server.init();
             let mut client_count = 0usize;
             let mut stepnum = 0;
+            // The first client to issue a control command becomes the controller; commands from
+            // anyone else are ignored until it disconnects.
+            let mut controller: Option<Team> = None;
+            let mut playback = Playback::new(clock, Instant::now());
             while !world.finished() {
                 loop {
-                    let rc = if client_count == 0 {
-                        Ok(rx.recv().unwrap())
+                    let rc = if client_count == 0 || playback.idle() {
+                        let msg = rx.recv().unwrap();
+                        // Time spent waiting (for clients, or while paused) isn't caught up on
+                        playback.woke(Instant::now());
+                        Ok(msg)
                     } else {
                         rx.try_recv()
                     };
                     match rc {
-                        Ok(ClientMessage::Connect(team, addr)) => {
-                            println!("Connection from {}, team {}", addr.unwrap(), team);
+                        Ok(ClientMessage::Connect(team, addr, version)) => {
+                            println!("Connection from {}, team {}, protocol {}", addr.unwrap(), team, version);
                             client_count += 1;
                         },
                         Ok(ClientMessage::Disconnect(team)) => {
                             println!("Team {} disconnected", team);
                             client_count -= 1;
+                            if controller == Some(team) {
+                                controller = None;
+                            }
+                        },
+                        Ok(ClientMessage::Command(team, cmd)) => {
+                            if controller.is_none() {
+                                println!("Team {} took control", team);
+                                controller = Some(team);
+                            }
+                            if controller != Some(team) {
+                                println!("Ignoring {:?} from team {}, which is not the controller", cmd, team);
+                                continue;
+                            }
+                            match cmd {
+                                ControlCommand::Pause => playback.pause(),
+                                ControlCommand::Resume => playback.resume(),
+                                ControlCommand::Step => playback.step(),
+                                ControlCommand::Strike { x, y, r } => {
+                                    println!("Team {} called a strike at ({}, {})", team, x, y);
+                                    world.explode(Pair { x, y }, r);
+                                },
+                            }
+                        },
+                        Ok(ClientMessage::Message(team, msg)) => {
+                            // Nothing consumes tank messages yet, but don't let them go to a tank
+                            // that isn't there
+                            if world.tank_by_team(team).is_none() {
+                                println!("Dropping {:?} from team {}, which has no live tank", msg, team);
+                            }
                         },
                         Err(_) => break,
-                        _ => (),
                     }
                 }
-                world.step();
-                println!("Step: {}", stepnum);
-                let bcast = OwnedMessage::Text(serde_json::to_string(&UpdatePacket {
-                    tanks: &*world.tanks.read().unwrap(),
-                    bullets: &*world.bullets.read().unwrap(),
-                })
-                .unwrap());
-                server.broadcaster().broadcast(bcast);
-                sleep(DELAY_DURATION);
-                stepnum += 1;
-                //eprintln!("---\n{:?}", world);
+                let ticks = playback.ticks(Instant::now());
+                for _ in 0..ticks {
+                    if world.finished() {
+                        break;
+                    }
+                    world.step();
+                    let json = update_json(&world);
+                    log_step(verbosity, stepnum, || json.clone());
+                    server.broadcaster().broadcast(OwnedMessage::Text(json));
+                    server.set_status(world.status());
+                    stepnum += 1;
+                    //eprintln!("---\n{:?}", world);
+                }
+                if let Some(wait) = playback.until_next() {
+                    sleep(wait);
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use serde::Deserialize;
+
+    use RANKS::sim::{BulletKind, TankInfo};
+
+    // Options for one sample tank per name, as if each had been given on the command line.
+    fn options(names: &[&str]) -> Options {
+        Options {
+            tps: DEFAULT_TPS,
+            copies: 1,
+            steps: DEFAULT_BENCH_STEPS,
+            verbosity: None,
+            facing: None,
+            names: names.iter().map(|&n| n.into()).collect(),
+            progs: names.iter().map(|_| SAMPLE_PROGRAM.to_vec()).collect(),
+            starts: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn team_metadata_reaches_the_update_packet() {
+        let world = options(&["alpha", "beta"]).build_world();
+        assert_eq!(world.config.teams[&1].name, "beta");
+        assert_eq!(world.config.teams[&1].color, TEAM_COLORS[1]);
+        let update: serde_json::Value = serde_json::from_str(&update_json(&world)).unwrap();
+        assert_eq!(update["teams"]["0"]["name"], "alpha");
+        assert_eq!(update["teams"]["1"]["color"], TEAM_COLORS[1]);
+    }
+
+    #[test]
+    fn bench_times_every_step_until_the_match_ends() {
+        let mut world = options(&["a", "b"]).build_world();
+        assert_eq!(time_steps(&mut world, 10).len(), 10);
+        assert_eq!(world.steps, 10);
+        // Nothing left running, nothing to time
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        assert!(time_steps(&mut world, 10).is_empty());
+    }
+
+    #[test]
+    fn every_tank_spawns_inside_the_world() {
+        let mut options = options(&["a", "b", "c", "d"]);
+        options.copies = 3;
+        let world = options.build_world();
+        let tanks = world.tanks.read().unwrap();
+        assert_eq!(tanks.len(), 12);
+        for t in tanks.iter() {
+            let pos = t.read().unwrap().pos;
+            assert_eq!(pos.clamp_to(&world.config.world_bound), pos);
+        }
+    }
+
+    #[test]
+    fn start_specs_parse() {
+        let (team, start) = parse_start("1=100,-50.5,1.5,40,70").unwrap();
+        assert_eq!(team, 1);
+        assert_eq!(start.spawn, Pair { x: 100.0, y: -50.5 });
+        assert_eq!((start.angle, start.temp, start.hp), (1.5, 40, 70));
+        assert!(parse_start("1=100,-50,1.5,40").is_none());
+        assert!(parse_start("1=100,-50,1.5,40,70,0").is_none());
+        assert!(parse_start("100,-50,1.5,40,70").is_none());
+        assert!(parse_start("x=100,-50,1.5,40,70").is_none());
+        assert!(parse_start("1=100,-50,1.5,hot,70").is_none());
+    }
+
+    #[test]
+    fn teams_with_a_start_spawn_there() {
+        let mut options = options(&["a", "b"]);
+        let (team, start) = parse_start("1=100,-50,1.5,40,70").unwrap();
+        options.starts.insert(team, start);
+        let world = options.build_world();
+        let tanks = world.tanks.read().unwrap();
+        let (plain, started) = (tanks[0].read().unwrap(), tanks[1].read().unwrap());
+        assert_eq!(started.team, 1);
+        assert_eq!(started.pos, Pair { x: 100.0, y: -50.0 });
+        assert_eq!((started.angle, started.aim), (1.5, 1.5));
+        assert_eq!((started.temp, started.health), (40, 70));
+        assert_eq!((plain.temp, plain.health), (0, world.config.tank_health));
+    }
+
+    #[test]
+    fn startup_packet_carries_hit_radii() {
+        let world = Configuration {
+            tank_radius: 7.0,
+            bullet_radius: 2.0,
+            heavy_radius: 4.5,
+            ..Configuration::default()
+        }
+        .build();
+        let startup = serde_json::to_value(&startup_packet(&world)).unwrap();
+        assert_eq!(startup["tank_radius"], 7.0);
+        assert_eq!(startup["bullet_radius"], 2.0);
+        assert_eq!(startup["heavy_bullet_radius"], 4.5);
+        assert_eq!(startup["arena"]["org"]["x"], -500.0);
+    }
+
+    #[test]
+    fn verbosity_decides_what_is_built_and_printed() {
+        assert!(Verbosity::parse("quiet") == Some(Verbosity::Quiet));
+        assert!(Verbosity::parse("steps") == Some(Verbosity::Steps));
+        assert!(Verbosity::parse("full") == Some(Verbosity::Full));
+        assert!(Verbosity::parse("loud").is_none());
+        // The update packet is only asked for when it will be printed
+        let built = Cell::new(0);
+        let json = || {
+            built.set(built.get() + 1);
+            String::from("{}")
+        };
+        log_step(Verbosity::Quiet, 0, json);
+        log_step(Verbosity::Steps, 1, json);
+        assert_eq!(built.get(), 0);
+        log_step(Verbosity::Full, 2, json);
+        assert_eq!(built.get(), 1);
+    }
+
+    #[test]
+    fn tanks_can_start_facing_the_center() {
+        assert!(Facing::parse("center") == Some(Facing::Center));
+        assert!(Facing::parse("east") == Some(Facing::East));
+        assert!(Facing::parse("north").is_none());
+        let mut options = options(&["a", "b", "c"]);
+        let world = options.build_world();
+        for t in world.tanks.read().unwrap().iter() {
+            let t = t.read().unwrap();
+            assert_eq!((t.angle, t.aim), (0.0, 0.0));
+        }
+        options.facing = Some(Facing::Center);
+        let world = options.build_world();
+        for t in world.tanks.read().unwrap().iter() {
+            let t = t.read().unwrap();
+            assert_eq!(t.angle, t.aim);
+            let inward = Pair::zero() + (-t.pos);
+            let heading = Pair::polar(t.angle);
+            assert!((heading.dot(&inward) / inward.mag() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    // Owned copies of the packets `schema` prints, as a client would read them. Unknown fields
+    // are refused, so anything added to a packet has to be added here too.
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct StartupMirror {
+        teams: HashMap<Team, TeamMeta>,
+        arena: AABB,
+        obstacles: Vec<AABB>,
+        zones: Vec<Zone>,
+        tank_radius: f32,
+        bullet_radius: f32,
+        heavy_bullet_radius: f32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct BulletMirror {
+        id: usize,
+        pos: Pair,
+        vel: Pair,
+        dead: bool,
+        owner: Option<usize>,
+        age: usize,
+        kind: BulletKind,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct UpdateMirror {
+        teams: HashMap<Team, TeamMeta>,
+        tanks: Vec<TankInfo>,
+        bullets: Vec<BulletMirror>,
+        effects: Vec<Effect>,
+    }
+
+    #[test]
+    fn schema_samples_read_back_as_documented() {
+        let world = schema_world();
+        let json = serde_json::to_string_pretty(&startup_packet(&world)).unwrap();
+        let startup: StartupMirror = serde_json::from_str(&json).unwrap();
+        assert_eq!(startup.teams[&0].name, "sample");
+        assert_eq!(startup.arena, world.arena_bounds());
+        assert_eq!((startup.obstacles.len(), startup.zones.len()), (0, 0));
+        assert_eq!(startup.tank_radius, world.config.tank_radius);
+        assert_eq!(startup.bullet_radius, world.config.bullet_radius);
+        assert_eq!(startup.heavy_bullet_radius, world.config.heavy_radius);
+
+        let update: UpdateMirror = serde_json::from_str(&update_json(&world)).unwrap();
+        assert_eq!(update.teams, startup.teams);
+        assert_eq!(update.tanks.len(), 1);
+        assert_eq!(update.tanks[0], world.tanks.read().unwrap()[0].read().unwrap().info());
+        let mirror = &update.bullets[0];
+        let bullet = world.bullets.read().unwrap()[0].read().unwrap().clone();
+        assert_eq!((mirror.id, mirror.owner, mirror.dead), (bullet.id, bullet.owner, bullet.dead));
+        assert_eq!((mirror.pos, mirror.vel), (bullet.pos, bullet.vel));
+        assert_eq!((mirror.age, mirror.kind), (bullet.age, bullet.kind));
+        assert!(update.effects.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use sim::{Configuration, World};
+use vm::{clamp_count, StepResult, Upcall, VM};
+
+// One upcall a tank made, along with whatever the host answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpcallRecord {
+    pub step: usize,
+    pub tank: usize,
+    pub upcall: Upcall,
+    // Bytes the host wrote into the tank's memory to answer, for calls like ScanInto that answer
+    // that way rather than through a return value
+    #[serde(default)]
+    pub written: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Instantiation(wasmi::Error),
+    // The program made a different call than the one recorded at `index`
+    Diverged {
+        index: usize,
+        expected: String,
+        got: String,
+    },
+    // The program returned before making the call recorded at `index`
+    Finished { index: usize },
+    // The program ran longer than any tank could have between the calls before and at `index`
+    Stalled { index: usize },
+}
+
+// Run `prog` in a fresh VM, answering its upcalls from `log` (one tank's records, in order), and
+// check that it makes exactly the recorded calls. The world plays no part: this only verifies
+// that the program behaves the same given the same inputs.
+pub fn replay_upcalls(prog: Vec<u8>, config: Arc<Configuration>, log: &[UpcallRecord]) -> Result<(), ReplayError> {
+    let namespace = config.import_namespace.clone();
+    // Most instructions a tank can be granted in one step, whatever the world's budgeting
+    let per_step = config.instrs_per_step.max(config.adaptive_budget.unwrap_or(0));
+    let mut vm = VM::new(prog, config, &namespace).map_err(ReplayError::Instantiation)?;
+    let mut last_step = log.first().map_or(0, |r| r.step);
+    for (index, record) in log.iter().enumerate() {
+        // The program can't have run for more steps than passed between the two calls
+        let steps = record.step.saturating_sub(last_step) + 1;
+        last_step = record.step;
+        vm.begin_step();
+        let got = match vm.run_until(Some(clamp_count(per_step.saturating_mul(steps)))) {
+            StepResult::Upcall(uc) => uc,
+            StepResult::Exhausted => return Err(ReplayError::Stalled { index }),
+            // Tanks treat a trap as a request to explode
+            StepResult::Trapped(_) | StepResult::Unanswered => Upcall::Explode,
+            StepResult::Finished => return Err(ReplayError::Finished { index }),
+        };
+        // Display covers the kind and arguments of a call, but not its result
+        let (expected, got_desc) = (record.upcall.to_string(), got.to_string());
+        if expected != got_desc {
+            return Err(ReplayError::Diverged {
+                index,
+                expected,
+                got: got_desc,
+            });
+        }
+        got.fill_from(&record.upcall);
+        if let (Upcall::ScanInto(_, _, ptr), Some(bytes)) = (&got, &record.written) {
+            // The VM checked the range when the call was made
+            core::mem::drop(vm.write_bytes(*ptr, bytes));
+        }
+    }
+    Ok(())
+}
+
+impl World {
+    // Replay everything tank `id` did this match against a fresh instance of `prog`.
+    pub fn replay_upcalls(&self, id: usize, prog: Vec<u8>) -> Result<(), ReplayError> {
+        let log: Vec<UpcallRecord> = self
+            .upcall_log()
+            .iter()
+            .filter(|r| r.tank == id)
+            .cloned()
+            .collect();
+        replay_upcalls(prog, self.config.clone(), &log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sim::{FireStatus, TankBuilder};
+    use space::Pair;
+    use vm::testprog::*;
+
+    // Forever: aim wherever GPS says our x is.
+    fn follower() -> Vec<u8> {
+        let body = [call(0), call(1)].concat();
+        Module::new(forever(&body)).with_imports(&["gpsx", "aim"]).build()
+    }
+
+    fn recorded_match() -> World {
+        let mut config = Configuration::default();
+        config.record_upcalls = true;
+        let mut world = config.build();
+        let tank = TankBuilder::new(Pair { x: 7.0, y: 0.0 }, 0, follower(), world.config.clone())
+            .build()
+            .unwrap();
+        world.add_tank(tank).unwrap();
+        for _ in 0..5 {
+            world.step();
+        }
+        world
+    }
+
+    #[test]
+    fn recorded_matches_replay_cleanly() {
+        let world = recorded_match();
+        assert!(world.upcall_log().len() >= 10);
+        world.replay_upcalls(0, follower()).unwrap();
+    }
+
+    #[test]
+    fn replay_says_where_a_program_went_wrong() {
+        let world = recorded_match();
+        let log = world.upcall_log().clone();
+        let config = world.config.clone();
+
+        let aimer = Module::new(forever(&[f32_const(1.0), call(0)].concat())).with_imports(&["aim"]);
+        match replay_upcalls(aimer.build(), config.clone(), &log) {
+            Err(ReplayError::Diverged { index: 0, .. }) => (),
+            other => panic!("{:?}", other),
+        }
+        match replay_upcalls(Module::new(vec![NOP]).build(), config.clone(), &log) {
+            Err(ReplayError::Finished { index: 0 }) => (),
+            other => panic!("{:?}", other),
+        }
+        match replay_upcalls(Module::new(forever(&[NOP])).build(), config.clone(), &log) {
+            Err(ReplayError::Stalled { index: 0 }) => (),
+            other => panic!("{:?}", other),
+        }
+        match replay_upcalls(vec![0, 1, 2, 3], config, &log) {
+            Err(ReplayError::Instantiation(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    // Forever: if GPS puts us right of the origin, fire, and aim somewhere else whenever the shot
+    // doesn't go off. Which calls come next hangs on what GPS and fire said.
+    fn gunner() -> Vec<u8> {
+        let aim_away = [f32_const(1.0), call(2)].concat();
+        let shoot = [call(1), if_then(&aim_away)].concat();
+        let body = [call(0), f32_const(0.0), vec![F32_GT], if_then(&shoot)].concat();
+        Module::new(forever(&body)).with_imports(&["gpsx", "fire", "aim"]).build()
+    }
+
+    // The results of every Fire in `log`, in order.
+    fn fire_results(log: &[UpcallRecord]) -> Vec<Option<i32>> {
+        log.iter()
+            .filter_map(|r| match r.upcall {
+                Upcall::Fire(ref rv) => Some(*rv.lock().unwrap()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replays_follow_what_fire_answered() {
+        let mut config = Configuration::default();
+        config.record_upcalls = true;
+        let mut world = config.build();
+        let tank = TankBuilder::new(Pair { x: 7.0, y: 0.0 }, 0, gunner(), world.config.clone())
+            .build()
+            .unwrap();
+        world.add_tank(tank).unwrap();
+        for _ in 0..5 {
+            world.step();
+        }
+        let log = world.upcall_log().clone();
+        // Shots went off, and others were refused for the cooldown, so both branches were taken
+        let results = fire_results(&log);
+        assert!(results.contains(&Some(FireStatus::Fired as i32)));
+        assert!(results.contains(&Some(FireStatus::Cooldown as i32)));
+        replay_upcalls(gunner(), world.config.clone(), &log).unwrap();
+
+        // Had the first shot been refused, the program would have aimed next instead
+        let index = log
+            .iter()
+            .position(|r| match r.upcall {
+                Upcall::Fire(ref rv) => *rv.lock().unwrap() == Some(FireStatus::Fired as i32),
+                _ => false,
+            })
+            .unwrap();
+        if let Upcall::Fire(ref rv) = log[index].upcall {
+            *rv.lock().unwrap() = Some(FireStatus::Cooldown as i32);
+        }
+        match replay_upcalls(gunner(), world.config.clone(), &log) {
+            Err(ReplayError::Diverged { index: at, .. }) => assert_eq!(at, index + 1),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// A tiny deterministic PRNG (xorshift64*). Matches need to be reproducible from a seed, so every
+// source of randomness in the simulation draws from one of these rather than from the OS.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+const GOLDEN: u64 = 0x9E37_79B9_7F4A_7C15;
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift gets stuck at zero, so never start there
+        Rng {
+            state: if seed == 0 { GOLDEN } else { seed },
+        }
+    }
+
+    // Derive an independent stream, e.g. one per tank from the world seed.
+    pub fn stream(seed: u64, stream: u64) -> Rng {
+        Rng::new(seed ^ stream.wrapping_add(1).wrapping_mul(GOLDEN))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // Uniform in [-1, 1)
+    pub fn next_signed(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+
+    // Roughly normal, with mean 0 and variance 1, but never beyond ±3: the sum of three uniform
+    // draws. Cheap, and bounded is what the simulation wants anyway.
+    pub fn next_gaussian(&mut self) -> f32 {
+        self.next_signed() + self.next_signed() + self.next_signed()
+    }
+}
@@ -1,15 +1,26 @@
 
-use std::net::{TcpListener, SocketAddr};
+use std::collections::BTreeSet;
+use std::net::{TcpListener, TcpStream, SocketAddr};
 use std::thread::{self, JoinHandle};
-use std::sync::{Mutex, MutexGuard, Arc, mpsc::{self, RecvError}};
+use std::sync::{Mutex, MutexGuard, Arc, mpsc::{self, RecvTimeoutError}};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use std::io::Result as IoResult;
 
-use websocket::{OwnedMessage, server::{NoTlsAcceptor, WsServer}, result::WebSocketError};
+use websocket::{CloseData, OwnedMessage, server::{NoTlsAcceptor, WsServer}, result::WebSocketError, sync::Client};
 
-use bus::Bus;
+use bus::{Bus, BusReader};
 
-use sim::Team;
+use serde::{Deserialize, Serialize};
+
+use sim::{MatchStatus, Team};
+
+// Bump whenever a packet changes shape; clients speaking any other version are turned away.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// How long a client gets to send its Hello before it's dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct TankServer {
     // TODO: use a TLS acceptor, but it's almost midnight and I don't want to do it now
@@ -18,6 +29,7 @@ pub struct TankServer {
     stm: Arc<OwnedMessage>,
     receiver: Option<mpsc::Receiver<ClientMessage>>,
     tx: mpsc::Sender<ClientMessage>,
+    status: Arc<Mutex<MatchStatus>>, // Kept current by whoever steps the world; see set_status
 }
 
 impl TankServer {
@@ -35,12 +47,19 @@ impl TankServer {
         rv
     }
 
-    pub fn new(startup_message: Arc<OwnedMessage>) -> std::io::Result<Self> {
+    // What clients are told when they ask for the status. The world can't be shared with the
+    // client threads, so the stepping loop hands over a fresh summary after every step instead.
+    pub fn set_status(&self, status: MatchStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    pub fn new(startup_message: Arc<OwnedMessage>, status: MatchStatus) -> std::io::Result<Self> {
         WsServer::<NoTlsAcceptor, TcpListener>::bind(SocketAddr::from(([0, 0, 0, 0], 7446))).map(|wsserv| {
             let ws = Arc::new(Mutex::new(wsserv));
             let bus = Arc::new(Mutex::new(Bus::new(10)));
             let (tx, receiver) = mpsc::channel();
-            TankServer { wsserv: ws, broadcaster: bus, stm: startup_message, receiver: Some(receiver), tx }
+            let status = Arc::new(Mutex::new(status));
+            TankServer { wsserv: ws, broadcaster: bus, stm: startup_message, receiver: Some(receiver), tx, status }
         })
     }
 
@@ -49,49 +68,75 @@ impl TankServer {
         let stm = self.stm.clone();
         let tx = self.tx.clone();
         let rxsource = self.broadcaster.clone();
+        let status = self.status.clone();
         thread::spawn(move || {
-            let mut team: Team = 0;
+            let free_teams: Arc<Mutex<BTreeSet<Team>>> = Arc::new(Mutex::new(BTreeSet::new()));
+            let mut next_team: Option<Team> = Some(0);
             loop {
                 match wsc.lock().unwrap().accept() {
                     Ok(u) => match u.accept() {
                         Ok(mut client) => {
-                            let my_team = team.clone();
+                            let slot = match claim_team(&free_teams, &mut next_team) {
+                                Some(slot) => slot,
+                                None => {
+                                    println!("Out of team numbers; turning a client away");
+                                    continue;
+                                }
+                            };
+                            let my_team = slot.team;
                             let to_send = stm.clone();
-                            let mut rx = rxsource.lock().unwrap().add_rx();
+                            let rxsource = rxsource.clone();
                             let my_tx = tx.clone();
+                            let msg_tx = tx.clone();
                             let dc_tx = tx.clone();
-                            match my_tx.send(ClientMessage::Connect(my_team, client.peer_addr())) {
-                                _ => ()  // TODO: maybe do something if the channel is closed?
-                            };
+                            let status = status.clone();
                             thread::spawn(move || {
+                                let _slot = slot;
+                                if admit(&mut client, my_team, &my_tx).is_none() {
+                                    return;
+                                }
+                                // Only now, so a client that never gets through can't leave a
+                                // receiver on the bus
+                                let rx = rxsource.lock().unwrap().add_rx();
                                 match || -> Result<(), WebSocketError> {
                                     client.send_message(&*to_send)?;
                                     let (mut reader, mut writer) = client.split().map_err(|e| WebSocketError::IoError(e))?;
+                                    // Set once the client is gone; see write_to_client
+                                    let closed = Arc::new(AtomicBool::new(false));
+                                    let reader_closed = closed.clone();
+                                    // Answers meant for this client alone, as opposed to the bus
+                                    let (reply_tx, reply_rx) = mpsc::channel::<OwnedMessage>();
                                     let jh1 = thread::spawn(move || {
-                                        loop {
-                                            match rx.recv() {
-                                                Ok(message) => {
-                                                    match writer.send_message(&message) {
-                                                        Ok(()) => (),
-                                                        Err(_) => break,
-                                                    }
-                                                }
-                                                Err(RecvError) => {
-                                                    core::mem::drop(writer.shutdown_all());
-                                                    break;
-                                                }
-                                            }
-                                        }
+                                        let send = |message: &OwnedMessage| writer.send_message(message);
+                                        write_to_client(send, rx, reply_rx, &closed);
+                                        core::mem::drop(writer.shutdown_all());
                                     });
                                     let jh2 = thread::spawn(move || {
                                         loop {
-                                            match reader.recv_message() {
-                                                Ok(_message) => {
-                                                    // TODO
+                                            let message = match reader.recv_message() {
+                                                Ok(OwnedMessage::Close(_)) => break,
+                                                Ok(OwnedMessage::Text(text)) => {
+                                                    // Queries are answered here, without
+                                                    // bothering the stepping loop
+                                                    if let Ok(Query::Status) = serde_json::from_str::<Query>(&text) {
+                                                        let status = status.lock().unwrap().clone();
+                                                        let reply = serde_json::to_string(&StatusPacket { status }).unwrap();
+                                                        core::mem::drop(reply_tx.send(OwnedMessage::Text(reply)));
+                                                        continue;
+                                                    }
+                                                    match serde_json::from_str::<ControlCommand>(&text) {
+                                                        Ok(cmd) => ClientMessage::Command(my_team, cmd),
+                                                        Err(_) => ClientMessage::Message(my_team, OwnedMessage::Text(text)),
+                                                    }
                                                 },
+                                                Ok(message) => ClientMessage::Message(my_team, message),
                                                 Err(_) => break,
+                                            };
+                                            if msg_tx.send(message).is_err() {
+                                                break;
                                             }
                                         }
+                                        reader_closed.store(true, Ordering::SeqCst);
                                     });
                                     core::mem::drop((jh1.join(), jh2.join()));
                                     Ok(())
@@ -106,15 +151,320 @@ impl TankServer {
                     },
                     Err(_) => continue,
                 }
-                team = team.wrapping_add(1);
             }
         })
     }
 }
 
+// Pass broadcasts from `rx`, and answers from `replies`, on to a client through `send`, until
+// sending fails, the bus goes away, or `closed` is set. The receiver is dropped on the way out:
+// one left on the bus with nobody reading it would eventually hold up every broadcast.
+fn write_to_client<F>(
+    mut send: F,
+    mut rx: BusReader<OwnedMessage>,
+    replies: mpsc::Receiver<OwnedMessage>,
+    closed: &AtomicBool,
+) where
+    F: FnMut(&OwnedMessage) -> Result<(), WebSocketError>,
+{
+    loop {
+        if let Ok(message) = replies.try_recv() {
+            match send(&message) {
+                Ok(()) => continue,
+                Err(_) => break,
+            }
+        }
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(message) => {
+                if send(&message).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if closed.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    core::mem::drop(rx);
+}
+
+// A client's claim on its team; the team is free for the next client once this is dropped.
+struct TeamSlot {
+    team: Team,
+    free: Arc<Mutex<BTreeSet<Team>>>,
+}
+
+impl Drop for TeamSlot {
+    fn drop(&mut self) {
+        self.free.lock().unwrap().insert(self.team);
+    }
+}
+
+// Teams given up by departed clients are handed out again, lowest first, before any new ones.
+// Handing out a team twice would mix two clients up, so if none are left this gives None.
+fn claim_team(free: &Arc<Mutex<BTreeSet<Team>>>, next_team: &mut Option<Team>) -> Option<TeamSlot> {
+    let freed = free.lock().unwrap().iter().next().cloned();
+    let team = match freed {
+        Some(team) => {
+            free.lock().unwrap().remove(&team);
+            team
+        }
+        None => {
+            let team = (*next_team)?;
+            *next_team = team.checked_add(1);
+            team
+        }
+    };
+    Some(TeamSlot { team, free: free.clone() })
+}
+
+// The first frame a client must send.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol: u32,
+}
+
+// Handshake with a freshly accepted client, and tell the stepping loop about it if it gets
+// through.
+fn admit(client: &mut Client<TcpStream>, team: Team, tx: &mpsc::Sender<ClientMessage>) -> Option<u32> {
+    let version = handshake(client)?;
+    // TODO: maybe do something if the channel is closed?
+    core::mem::drop(tx.send(ClientMessage::Connect(team, client.peer_addr(), version)));
+    Some(version)
+}
+
+// Wait for the client's Hello. If it doesn't send one in time, or names another version, it gets
+// a close frame saying which version is required.
+fn handshake(client: &mut Client<TcpStream>) -> Option<u32> {
+    let version = client
+        .stream_ref()
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .ok()
+        .and_then(|()| client.recv_message().ok())
+        .and_then(|msg| hello_version(&msg));
+    // Past the handshake, clients may stay quiet for as long as they like
+    if version == Some(PROTOCOL_VERSION) && client.stream_ref().set_read_timeout(None).is_ok() {
+        return version;
+    }
+    let reason = format!("protocol version {} required", PROTOCOL_VERSION);
+    core::mem::drop(client.send_message(&OwnedMessage::Close(Some(CloseData::new(1002, reason)))));
+    None
+}
+
+// The protocol version a client's opening frame names, if it's a Hello at all.
+fn hello_version(msg: &OwnedMessage) -> Option<u32> {
+    match msg {
+        OwnedMessage::Text(text) => serde_json::from_str::<Hello>(text).ok().map(|h| h.protocol),
+        _ => None,
+    }
+}
+
 pub enum ClientMessage {
-    Connect(Team, IoResult<SocketAddr>),
+    Connect(Team, IoResult<SocketAddr>, u32), // With the protocol version agreed on
     Disconnect(Team),
+    Command(Team, ControlCommand),
     Message(Team, OwnedMessage),
 }
 
+// Text frames of the form {"cmd": "..."} are parsed into these; anything else is passed through
+// as a ClientMessage::Message.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    Step,
+    Strike { x: f32, y: f32, r: f32 }, // An explosion of radius `r` at (x, y), on the next step
+}
+
+// Like ControlCommand, but any client may send these, and they don't take control.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+enum Query {
+    Status, // Answered with a StatusPacket
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusPacket {
+    pub status: MatchStatus,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_packet_round_trips() {
+        let mut status = MatchStatus::default();
+        status.step = 42;
+        status.living.insert(0, 2);
+        status.living.insert(3, 0);
+        status.tanks = 3;
+        status.bullets = 7;
+        let packet = StatusPacket { status };
+        let json = serde_json::to_string(&packet).unwrap();
+        assert_eq!(serde_json::from_str::<StatusPacket>(&json).unwrap(), packet);
+    }
+
+    #[test]
+    fn status_query_parses() {
+        match serde_json::from_str::<Query>(r#"{"cmd": "status"}"#) {
+            Ok(Query::Status) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn playback_commands_parse() {
+        let parse = |text: &str| serde_json::from_str::<ControlCommand>(text);
+        match (parse(r#"{"cmd": "pause"}"#), parse(r#"{"cmd": "resume"}"#), parse(r#"{"cmd": "step"}"#)) {
+            (Ok(ControlCommand::Pause), Ok(ControlCommand::Resume), Ok(ControlCommand::Step)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        // Anything else goes through to the stepping loop as an ordinary message
+        assert!(parse(r#"{"cmd": "rewind"}"#).is_err());
+        assert!(parse("pause").is_err());
+    }
+
+    #[test]
+    fn hellos_name_a_protocol_version() {
+        let text = |s: &str| OwnedMessage::Text(s.into());
+        assert_eq!(hello_version(&text(r#"{"protocol": 1}"#)), Some(1));
+        assert_eq!(hello_version(&text(r#"{"protocol": 7}"#)), Some(7));
+        let hello = serde_json::to_string(&Hello { protocol: PROTOCOL_VERSION }).unwrap();
+        assert_eq!(hello_version(&text(&hello)), Some(PROTOCOL_VERSION));
+        assert_eq!(hello_version(&text(r#"{"cmd": "pause"}"#)), None);
+        assert_eq!(hello_version(&text("hello")), None);
+        assert_eq!(hello_version(&OwnedMessage::Binary(hello.into_bytes())), None);
+    }
+
+    #[test]
+    fn departed_clients_give_their_teams_back() {
+        let free = Arc::new(Mutex::new(BTreeSet::new()));
+        let mut next = Some(0);
+        let first = claim_team(&free, &mut next).unwrap();
+        let second = claim_team(&free, &mut next).unwrap();
+        let third = claim_team(&free, &mut next).unwrap();
+        assert_eq!((first.team, second.team, third.team), (0, 1, 2));
+        // The lowest freed team goes out first, then new ones resume where they left off
+        core::mem::drop(third);
+        core::mem::drop(second);
+        let again = claim_team(&free, &mut next).unwrap();
+        let after = claim_team(&free, &mut next).unwrap();
+        let fresh = claim_team(&free, &mut next).unwrap();
+        assert_eq!((again.team, after.team, fresh.team), (1, 2, 3));
+        assert!(free.lock().unwrap().is_empty());
+
+        // With every number handed out, clients are turned away until one comes back
+        let free = Arc::new(Mutex::new(BTreeSet::new()));
+        let mut next = Some(Team::max_value());
+        let last = claim_team(&free, &mut next).unwrap();
+        assert_eq!(last.team, Team::max_value());
+        assert!(claim_team(&free, &mut next).is_none());
+        core::mem::drop(last);
+        assert_eq!(claim_team(&free, &mut next).map(|slot| slot.team), Some(Team::max_value()));
+    }
+
+    #[test]
+    fn strike_commands_parse() {
+        let parse = |text: &str| serde_json::from_str::<ControlCommand>(text);
+        match parse(r#"{"cmd": "strike", "x": 10.5, "y": -20, "r": 30}"#) {
+            Ok(ControlCommand::Strike { x, y, r }) => assert_eq!((x, y, r), (10.5, -20.0, 30.0)),
+            other => panic!("unexpected {:?}", other),
+        }
+        // A strike needs all three to go anywhere
+        assert!(parse(r#"{"cmd": "strike", "x": 10.5, "y": -20}"#).is_err());
+        assert!(parse(r#"{"cmd": "strike", "x": "here", "y": -20, "r": 30}"#).is_err());
+    }
+
+    #[test]
+    fn teams_stay_distinct_past_a_byte() {
+        let free = Arc::new(Mutex::new(BTreeSet::new()));
+        let mut next = Some(0);
+        let slots: Vec<TeamSlot> = (0..300).map(|_| claim_team(&free, &mut next).unwrap()).collect();
+        let teams: BTreeSet<Team> = slots.iter().map(|slot| slot.team).collect();
+        assert_eq!(teams.len(), slots.len());
+    }
+
+    // Connect a client to a loopback server, have it open by claiming `version`, and admit it on
+    // the server's end. Gives what the server made of it, what the stepping loop was told, and
+    // the first frame the client got back.
+    fn admit_over_loopback(version: u32) -> (Option<u32>, Option<ClientMessage>, Option<OwnedMessage>) {
+        let mut server = WsServer::<NoTlsAcceptor, TcpListener>::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        let peer = thread::spawn(move || {
+            let mut client = websocket::ClientBuilder::new(&url).unwrap().connect_insecure().unwrap();
+            let hello = serde_json::to_string(&Hello { protocol: version }).unwrap();
+            client.send_message(&OwnedMessage::Text(hello)).unwrap();
+            client.recv_message().ok()
+        });
+        let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let admitted = admit(&mut client, 3, &tx);
+        // Hang up, so a client that got through stops waiting for a frame
+        core::mem::drop(client.shutdown());
+        let reply = peer.join().unwrap();
+        (admitted, rx.try_recv().ok(), reply)
+    }
+
+    #[test]
+    fn only_clients_speaking_our_version_get_in() {
+        match admit_over_loopback(PROTOCOL_VERSION + 1) {
+            (None, None, Some(OwnedMessage::Close(Some(close)))) => {
+                assert_eq!(close.status_code, 1002);
+                assert_eq!(close.reason, format!("protocol version {} required", PROTOCOL_VERSION));
+            }
+            (admitted, _, reply) => panic!("admitted as {:?}, got {:?}", admitted, reply),
+        }
+        match admit_over_loopback(PROTOCOL_VERSION) {
+            (Some(admitted), Some(ClientMessage::Connect(team, Ok(_), version)), _) => {
+                assert_eq!((admitted, team, version), (PROTOCOL_VERSION, 3, PROTOCOL_VERSION));
+            }
+            (admitted, _, _) => panic!("turned away, with {:?}", admitted),
+        }
+    }
+
+    #[test]
+    fn writers_let_go_of_the_bus_once_their_client_leaves() {
+        let mut bus = Bus::new(2);
+        for _ in 0..2 {
+            let rx = bus.add_rx();
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let closed = Arc::new(AtomicBool::new(false));
+            let sent = Arc::new(Mutex::new(Vec::new()));
+            let writer = {
+                let (closed, sent) = (closed.clone(), sent.clone());
+                thread::spawn(move || {
+                    let send = |message: &OwnedMessage| {
+                        sent.lock().unwrap().push(message.clone());
+                        Ok(())
+                    };
+                    write_to_client(send, rx, reply_rx, &closed);
+                })
+            };
+            reply_tx.send(OwnedMessage::Text("reply".into())).unwrap();
+            bus.broadcast(OwnedMessage::Text("broadcast".into()));
+            for _ in 0..100 {
+                if sent.lock().unwrap().len() == 2 {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let mut sent = sent.lock().unwrap().clone();
+            sent.sort_by_key(|message| format!("{:?}", message));
+            let expected = vec![OwnedMessage::Text("broadcast".into()), OwnedMessage::Text("reply".into())];
+            assert_eq!(sent, expected);
+            closed.store(true, Ordering::SeqCst);
+            writer.join().unwrap();
+            // With the receiver gone, nothing is left to fill the bus up; a receiver left behind
+            // would refuse the third of these
+            for _ in 0..3 {
+                assert!(bus.try_broadcast(OwnedMessage::Text("nobody".into())).is_ok());
+            }
+        }
+    }
+}
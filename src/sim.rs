@@ -1,14 +1,36 @@
-use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
 
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
 
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Serialize, Serializer};
 
+use collision::*;
+use replay::UpcallRecord;
+use rng::Rng;
 use space::*;
 use vm::*;
 
-pub type Team = u8;
+// Wide enough for large tournaments; nothing else should assume its size.
+pub type Team = u16;
+
+// Display information for a team, passed through to clients untouched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamMeta {
+    pub name: String,
+    pub color: String,
+}
+
+// How a team's tanks start out, for asymmetric scenarios.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamStart {
+    pub spawn: Pair,
+    pub angle: f32, // Both heading and aim
+    pub temp: i32,
+    pub hp: i32,
+}
 
 pub trait Entity {
     fn step(&mut self, world: &World);
@@ -16,20 +38,53 @@ pub trait Entity {
 
 #[derive(Debug, Clone)]
 pub struct Tank {
+    pub id: usize,
     pub pos: Pair,
+    pub prev_pos: Pair, // Position at the start of the current step
+    pub vel: Pair, // How far the tank moved in its last step
     pub instrs_per_step: usize,
     pub aim: f32,
     pub angle: f32,
+    pub prev_angle: f32, // Heading at the start of the current step
     pub team: Team,
     pub temp: i32,
-    pub vm: VM,
+    pub vm: Option<VM>, // None for dummies, which have no program
     pub state: TankState,
-    pub timers: [usize; 1],
+    pub timers: [usize; 2], // Cooldowns: world-altering upcalls, then scans
+    pub scan_cache: Option<u64>, // Result of the last scan that ran, given back while scanning cools down
+    pub rng: Rng,
+    pub dead_since: Option<usize>, // Step at which the tank was first seen dead, for despawning
+    pub trace: VecDeque<(usize, Upcall)>, // The last `upcall_trace_len` upcalls, with their step
+    pub trace_public: bool, // Whether the trace goes out with the serialized info
+    pub throttle: f32, // Fraction of `tank_v` the tank drives at every step, in [-1, 1]
+    pub layers: u32, // Collision layer mask; only entities sharing a layer can collide
+    pub health: i32, // Only matters under CollisionRule::Damage; the tank dies at 0
+    pub arming: Option<usize>, // Steps left until this tank explodes, once it has asked to
+    pub protection: usize, // Steps left during which collisions and explosions can't hurt it
+    pub label: Option<String>, // Set by the tank itself, for spectators
+    pub idle_steps: usize, // Consecutive steps the tank ran without making an upcall
+    pub scans: usize, // Enemy scans that have caught this tank so far this step
+    pub scanned: usize, // Enemy scans that caught it last step, for `was_scanned`
+    pub death_cause: Option<DeathCause>,
 }
 
-#[derive(Debug, Clone)]
+// What killed a tank; the first thing to do so, if several did at once.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeathCause {
+    Collision,
+    Explosion,
+    Boundary, // Left the arena under BoundaryBehavior::Kill
+    Unresponsive, // Went `idle_timeout_steps` without an upcall
+    SelfDestruct, // Blew itself up by choice
+    Fault, // Its program trapped, or couldn't be resumed
+    Overheat, // Reached `death_heat` and blew up
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TankState {
     Dead,
+    Halted, // The program returned; the tank sits idle but can still be hit
     Free,
     Pending(Upcall),
 }
@@ -38,6 +93,7 @@ impl PartialEq for TankState {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (TankState::Dead, TankState::Dead) => true,
+            (TankState::Halted, TankState::Halted) => true,
             (TankState::Free, TankState::Free) => true,
             _ => false,
         }
@@ -69,14 +125,30 @@ where
     }
 }
 
-#[derive(Serialize)]
-struct TankSerInfo {
-    pos: Pair,
-    angle: f32,
-    aim: f32,
-    temp: i32,
-    team: Team,
-    dead: bool,
+// The externally visible state of a tank; this is what gets serialized and snapshotted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TankInfo {
+    pub id: usize,
+    pub pos: Pair,
+    pub vel: Pair,
+    pub angle: f32,
+    pub aim: f32,
+    pub temp: i32,
+    pub team: Team,
+    pub dead: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub death_cause: Option<DeathCause>,
+    pub health: i32,
+    pub arming: Option<usize>,
+    pub protected: bool, // Still within its spawn protection
+    pub cooldowns: Vec<usize>, // Steps left on each of the tank's timers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    // The upcall the tank is held up on by a cooldown, as its Display text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upcalls: Option<Vec<String>>,
 }
 
 impl Serialize for Tank {
@@ -84,33 +156,401 @@ impl Serialize for Tank {
     where
         S: Serializer,
     {
-        let info = TankSerInfo {
+        self.info().serialize(s)
+    }
+}
+
+pub struct TankBuilder {
+    pub pos: Pair,
+    pub team: Team,
+    pub aim: f32,
+    pub angle: f32,
+    pub temp: i32,
+    pub instrs_per_step: usize,
+    pub layers: u32,
+    pub health: i32,
+    prog: Vec<u8>,
+    config: Arc<Configuration>,
+}
+
+impl TankBuilder {
+    // If the team has a TeamStart configured, it takes the place of `pos` and the defaults. The
+    // config is shared with the world (see World::config), not copied.
+    pub fn new(pos: Pair, team: Team, prog: Vec<u8>, config: Arc<Configuration>) -> TankBuilder {
+        let builder = TankBuilder {
+            pos: pos,
+            team: team,
+            aim: 0.0,
+            angle: 0.0,
+            temp: 0,
+            instrs_per_step: config.instrs_per_step,
+            layers: config.default_layers,
+            health: config.tank_health,
+            prog: prog,
+            config: config,
+        };
+        match builder.config.team_starts.get(&team).cloned() {
+            Some(start) => builder.with_start(&start),
+            None => builder,
+        }
+    }
+
+    pub fn with_start(self, start: &TeamStart) -> TankBuilder {
+        TankBuilder {
+            pos: start.spawn,
+            angle: start.angle,
+            aim: start.angle,
+            temp: start.temp,
+            health: start.hp,
+            ..self
+        }
+    }
+
+    pub fn with_health(self, health: i32) -> TankBuilder {
+        TankBuilder {
+            health: health,
+            ..self
+        }
+    }
+
+    pub fn with_aim(self, aim: f32) -> TankBuilder {
+        TankBuilder { aim: aim, ..self }
+    }
+
+    pub fn with_angle(self, angle: f32) -> TankBuilder {
+        TankBuilder {
+            angle: angle,
+            ..self
+        }
+    }
+
+    pub fn with_temp(self, temp: i32) -> TankBuilder {
+        TankBuilder { temp: temp, ..self }
+    }
+
+    pub fn with_instrs_per_step(self, instrs_per_step: usize) -> TankBuilder {
+        TankBuilder {
+            instrs_per_step: instrs_per_step,
+            ..self
+        }
+    }
+
+    pub fn with_layers(self, layers: u32) -> TankBuilder {
+        TankBuilder {
+            layers: layers,
+            ..self
+        }
+    }
+
+    // Compiles and instantiates the program; this is where a bad module is reported.
+    pub fn build(self) -> Result<Tank, wasmi::Error> {
+        // Reseeded by id once the tank joins a world
+        let rng = Rng::new(self.config.seed);
+        let namespace = self.config.import_namespace.clone();
+        let vm = VM::new(self.prog, self.config, &namespace)?;
+        Ok(Tank {
+            id: 0,
             pos: self.pos,
-            angle: self.angle,
+            prev_pos: self.pos,
+            vel: Pair::zero(),
             aim: self.aim,
-            temp: self.temp,
+            angle: self.angle,
+            prev_angle: self.angle,
             team: self.team,
-            dead: self.state == TankState::Dead,
-        };
-        info.serialize(s)
+            instrs_per_step: self.instrs_per_step,
+            temp: self.temp,
+            vm: Some(vm),
+            state: TankState::Free,
+            timers: [0, 0],
+            scan_cache: None,
+            rng,
+            dead_since: None,
+            trace: VecDeque::new(),
+            trace_public: false,
+            throttle: 0.0,
+            layers: self.layers,
+            health: self.health,
+            arming: None,
+            protection: 0,
+            label: None,
+            idle_steps: 0,
+            scans: 0,
+            scanned: 0,
+            death_cause: None,
+        })
     }
 }
 
 impl Tank {
-    pub fn new(pos: Pair, team: Team, prog: Vec<u8>, config: Configuration) -> Result<Tank, wasmi::Error> {
-        let ips = config.instrs_per_step;
-        let vm = VM::new(prog, config)?;
-        Ok(Tank {
+    // A target with no program: it never acts, but can be scanned and hit like any other tank.
+    // It starts out halted, so it doesn't hold up the end of a match.
+    pub fn dummy(pos: Pair, team: Team) -> Tank {
+        Tank {
+            id: 0,
             pos,
+            prev_pos: pos,
+            vel: Pair::zero(),
+            instrs_per_step: 0,
             aim: 0.0,
             angle: 0.0,
-            team: team,
-            instrs_per_step: ips,
+            prev_angle: 0.0,
+            team,
             temp: 0,
-            vm,
-            state: TankState::Free,
-            timers: [0],
-        })
+            vm: None,
+            state: TankState::Halted,
+            timers: [0, 0],
+            scan_cache: None,
+            rng: Rng::new(0),
+            dead_since: None,
+            trace: VecDeque::new(),
+            trace_public: false,
+            throttle: 0.0,
+            layers: !0,
+            health: 1, // Any damage at all is fatal
+            arming: None,
+            protection: 0,
+            label: None,
+            idle_steps: 0,
+            scans: 0,
+            scanned: 0,
+            death_cause: None,
+        }
+    }
+
+    // Fire a bullet of `kind` along `aim`, if the gun will; cooldowns are handled by the caller.
+    fn fire(&mut self, world: &World, kind: BulletKind) -> FireStatus {
+        let mut status = self.fire_status(world, kind);
+        // Past `jam_heat` the gun gets unreliable: shots may jam, and those that get out scatter
+        // more
+        let overheated = world.config.jam_heat.map_or(false, |heat| self.temp > heat);
+        if status == FireStatus::Fired && overheated && self.rng.next_f32() < world.config.jam_chance {
+            status = FireStatus::Jammed;
+        }
+        if status != FireStatus::Fired {
+            return status;
+        }
+        self.apply_heat(world.config.shot_heat(kind));
+        let spread = if overheated {
+            world.config.bullet_spread + world.config.jam_spread
+        } else {
+            world.config.bullet_spread
+        };
+        // Aim assist swings the shot onto the nearest enemy close enough to the line of fire;
+        // spread still applies on top
+        let base = world
+            .config
+            .aim_assist_tolerance
+            .and_then(|tol| world.assist_bearing(self.pos, self.team, self.aim, tol))
+            .unwrap_or(self.aim);
+        // Always draw, so the stream doesn't depend on whether spread is enabled
+        let heading = base + self.rng.next_signed() * spread;
+        // Never spawn a bullet off the map, even when firing point-blank into an edge
+        let muzzle = self.pos + Pair::polar(self.aim) * world.config.bullet_s;
+        let spawn = muzzle.clamp_to(&world.config.world_bound);
+        let speed = world.config.bullet_v_of(kind);
+        let mut bullet = Bullet::new(spawn, Pair::polar(heading) * speed);
+        bullet.kind = kind;
+        bullet.layers = self.layers;
+        bullet.immune_steps = world.config.bullet_spawn_immunity;
+        bullet.owner = Some(self.id);
+        let bullet = world.add_bullet(bullet);
+        world.events.borrow_mut().push(GameEvent::Shot {
+            tank: self.id,
+            team: self.team,
+            bullet,
+        });
+        status
+    }
+
+    // Whether a shot of `kind` would go out right now, cooldowns aside; see FireStatus.
+    fn fire_status(&self, world: &World, kind: BulletKind) -> FireStatus {
+        if world.zones_at(self.pos).any(|z| z.effect == ZoneEffect::NoFire) {
+            return FireStatus::NoFireZone;
+        }
+        if let Some(limit) = world.config.fire_heat_limit {
+            if self.temp.saturating_add(world.config.shot_heat(kind)) > limit {
+                return FireStatus::TooHot;
+            }
+        }
+        if let Some(cap) = world.config.max_bullets_per_tank {
+            let live = world
+                .bullets
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|b| {
+                    let b = b.read().unwrap();
+                    !b.dead && b.owner == Some(self.id)
+                })
+                .count();
+            if live >= cap {
+                return FireStatus::BulletCap;
+            }
+        }
+        FireStatus::Fired
+    }
+
+    pub fn info(&self) -> TankInfo {
+        TankInfo {
+            id: self.id,
+            pos: self.pos,
+            vel: self.vel,
+            angle: self.angle,
+            aim: self.aim,
+            temp: self.temp,
+            team: self.team,
+            dead: self.state == TankState::Dead,
+            death_cause: self.death_cause,
+            health: self.health,
+            arming: self.arming,
+            protected: self.protection > 0,
+            cooldowns: self.timers.to_vec(),
+            label: self.label.clone(),
+            pending: match self.state {
+                TankState::Pending(ref uc) => Some(uc.to_string()),
+                _ => None,
+            },
+            upcalls: if self.trace_public {
+                Some(
+                    self.trace
+                        .iter()
+                        .map(|(step, uc)| format!("{}: {}", step, uc))
+                        .collect(),
+                )
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn recent_upcalls(&self) -> &VecDeque<(usize, Upcall)> {
+        &self.trace
+    }
+
+    // Count tanks between bearings `hl` and `hu` (in either order), packed as for the Scan
+    // upcall: ours in the high 32 bits, theirs in the low. The result is kept for cooldowns.
+    fn scan(&mut self, world: &World, hl: f32, hu: f32) -> u64 {
+        let (hl, hu) = (world.config.angle_unit.to_radians(hl), world.config.angle_unit.to_radians(hu));
+        let mut bounds = if hl < hu { (hl, hu) } else { (hu, hl) };
+        // A swept scan also covers where the arc was before this step's turning, as if the radar
+        // were fixed to the hull. The widened arc may cross 0; World::scan matches with in_arc,
+        // which wraps.
+        if world.config.swept_scan {
+            let turned = (self.angle - self.prev_angle + ::std::f32::consts::PI)
+                .rem_euclid(2.0 * ::std::f32::consts::PI)
+                - ::std::f32::consts::PI;
+            bounds = (bounds.0.min(bounds.0 - turned), bounds.1.max(bounds.1 - turned));
+        }
+        let (us, them) = world.scan(self.pos, self.team, bounds);
+        let result = ((us as u64) << 32) | them as u64;
+        self.scan_cache = Some(result);
+        result
+    }
+
+    // Unpack a scan result into the tank's memory at `ptr` as two little-endian u32s, ours then
+    // theirs. The VM checked that the range is in bounds when the upcall was made.
+    fn write_scan(&self, world: &World, ptr: u32, result: u64) {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&((result >> 32) as u32).to_le_bytes());
+        bytes[4..].copy_from_slice(&(result as u32).to_le_bytes());
+        if let Some(ref vm) = self.vm {
+            core::mem::drop(vm.write_bytes(ptr, &bytes));
+        }
+        world.record_written(self.id, &bytes);
+    }
+
+    // Error to add to one sensor reading. Always draws, like Fire, so the stream doesn't depend on
+    // whether noise is enabled.
+    fn sensor_noise(&mut self, config: &Configuration) -> f32 {
+        self.rng.next_gaussian() * config.sensor_noise
+    }
+
+    // Deal with the tank having left the arena (less its own radius) this step.
+    fn apply_boundary(&mut self, world: &World) {
+        let inner = world.config.world_bound.grow(-world.config.tank_radius);
+        if self.pos.clamp_to(&inner) == self.pos {
+            return;
+        }
+        match world.config.boundary {
+            BoundaryBehavior::Ignore => (),
+            BoundaryBehavior::Clamp => self.pos = self.pos.clamp_to(&inner),
+            BoundaryBehavior::Kill => self.kill(DeathCause::Boundary),
+            BoundaryBehavior::Bounce => {
+                // Reflect off whichever walls were crossed, turning the tank to match
+                let (lo, hi) = (inner.org, inner.opp());
+                if self.pos.x < lo.x || self.pos.x > hi.x {
+                    let wall = if self.pos.x < lo.x { lo.x } else { hi.x };
+                    self.pos.x = 2.0 * wall - self.pos.x;
+                    self.vel.x = -self.vel.x;
+                    self.angle = normalize_angle(::std::f32::consts::PI - self.angle).unwrap_or(self.angle);
+                }
+                if self.pos.y < lo.y || self.pos.y > hi.y {
+                    let wall = if self.pos.y < lo.y { lo.y } else { hi.y };
+                    self.pos.y = 2.0 * wall - self.pos.y;
+                    self.vel.y = -self.vel.y;
+                    self.angle = normalize_angle(-self.angle).unwrap_or(self.angle);
+                }
+                // A big enough step could bounce clean out the other side
+                self.pos = self.pos.clamp_to(&inner);
+            }
+        }
+    }
+
+    // Death is final: whatever the tank was waiting on or counting down to is dropped with it.
+    pub fn kill(&mut self, cause: DeathCause) {
+        if self.state != TankState::Dead {
+            self.death_cause = Some(cause);
+        }
+        self.state = TankState::Dead;
+        self.arming = None;
+        self.throttle = 0.0;
+    }
+
+    // The blast takes the tank with it, whatever its health or protection; the explosion itself
+    // only damages the others.
+    fn self_destruct(&mut self, world: &World, cause: DeathCause) {
+        self.kill(cause);
+        world.explode_from(self.id, self.pos, world.config.explode_rad);
+    }
+
+    // A program that faults blows up at once: no cooldown holds it back, and no arming delay
+    // gives it the look of a deliberate bomb. The log records it as the explosion that replays
+    // expect in its place.
+    fn fault(&mut self, world: &World) {
+        world.record_upcall(self.id, &Upcall::Explode);
+        self.self_destruct(world, DeathCause::Fault);
+    }
+
+    // Run the program for a step with every upcall answered from `rng` instead of the world,
+    // and nothing it asks for carried out; see Upcall::fill_random. Whatever bytes the program
+    // was built from and whatever it's told, the worst that should come of it is the tank's
+    // death, never a panic.
+    pub fn step_fuzz(&mut self, world: &World, rng: &mut Rng) {
+        if self.state == TankState::Dead {
+            return;
+        }
+        let budget = world.tank_budget().unwrap_or(self.instrs_per_step);
+        if let Some(ref mut vm) = self.vm {
+            vm.begin_step();
+        }
+        loop {
+            match self.vm.as_mut().map(|vm| vm.run_until(Some(clamp_count(budget)))) {
+                Some(StepResult::Upcall(Upcall::Explode)) => {
+                    self.self_destruct(world, DeathCause::SelfDestruct);
+                    break;
+                }
+                Some(StepResult::Upcall(Upcall::None)) | Some(StepResult::Exhausted) => break,
+                Some(StepResult::Upcall(uc)) => uc.fill_random(rng),
+                Some(StepResult::Finished) | None => {
+                    self.state = TankState::Halted;
+                    break;
+                }
+                Some(StepResult::Trapped(_)) | Some(StepResult::Unanswered) => {
+                    self.fault(world);
+                    break;
+                }
+            }
+        }
     }
 
     pub fn apply_heat(&mut self, heat: i32) {
@@ -122,25 +562,85 @@ impl Tank {
 }
 
 impl Entity for Tank {
+    // Metering: every step the VM is granted exactly `instrs_per_step` instructions, shared by
+    // all the run_until calls made during that step; unused instructions do not carry over.
+    // World-altering upcalls are separately rate-limited by `timers`, which count whole steps:
+    // servicing one arms its timer for `alter_cooldown` steps, and any issued while the timer is
+    // armed is parked in TankState::Pending (ending the tank's step) until it runs down. Scans
+    // of every kind share their own timer when `scan_cooldown` is set; plain scans answer from
+    // the last result while it runs, and the rest are parked like anything else.
     fn step(&mut self, world: &World) {
-        fn timer(uc: &Upcall, instrs_per_step: usize) -> Option<(usize, usize)> {
+        fn timer(uc: &Upcall, config: &Configuration) -> Option<(usize, usize)> {
             match uc {
-                uc if uc.alters_world() => Some((0, instrs_per_step)),
+                uc if uc.alters_world() => Some((0, config.alter_cooldown)),
+                Upcall::Scan(_, _, _)
+                | Upcall::ScanInto(_, _, _)
+                | Upcall::ScanBullets(_, _, _)
+                | Upcall::ScanHottest(_, _, _)
+                    if config.scan_cooldown > 0 =>
+                {
+                    Some((1, config.scan_cooldown))
+                }
                 _ => None,
             }
         }
-        self.apply_heat(world.config.idle_heat);
-        self.vm.begin_step();
+        // Nothing a dead tank left pending is ever carried out
+        if self.state == TankState::Dead {
+            return;
+        }
+        let budget = world.tank_budget().unwrap_or(self.instrs_per_step);
+        self.prev_pos = self.pos;
+        self.prev_angle = self.angle;
+        self.trace_public = world.config.trace_team == Some(self.team);
+        // An arming tank does nothing but count down to its blast
+        if let Some(left) = self.arming {
+            if left > 1 {
+                self.arming = Some(left - 1);
+            } else {
+                println!("tank commiting suicide!");
+                self.self_destruct(world, DeathCause::SelfDestruct);
+            }
+            return;
+        }
+        self.apply_heat(world.idle_heat());
+        for zone in world.zones_at(self.pos) {
+            if let ZoneEffect::Heal { rate } = zone.effect {
+                self.apply_heat(-rate);
+            }
+        }
+        if let Some(ref mut vm) = self.vm {
+            vm.begin_step();
+        }
         for timer in &mut self.timers {
-            *timer = timer.saturating_sub(self.instrs_per_step);
+            *timer = timer.saturating_sub(1);
         }
+        let (mut acted, mut exhausted, mut driven) = (false, false, false);
         loop {
             let uc;
-            match &mut self.state {
-                TankState::Free => {
-                    uc = self.vm.run_until(Some(self.instrs_per_step as isize));
-                }
-                TankState::Dead => break,
+            match self.state {
+                TankState::Free => match self.vm.as_mut().map(|vm| vm.run_until(Some(clamp_count(budget)))) {
+                    Some(StepResult::Upcall(upcall)) => uc = upcall,
+                    Some(StepResult::Exhausted) => {
+                        exhausted = true;
+                        break;
+                    }
+                    Some(StepResult::Finished) | None => {
+                        println!("tank finished");
+                        self.state = TankState::Halted;
+                        break;
+                    }
+                    Some(StepResult::Trapped(trap)) => {
+                        println!("tank trapped: {:?}", trap);
+                        self.fault(world);
+                        break;
+                    }
+                    Some(StepResult::Unanswered) => {
+                        println!("tank left waiting on an upcall");
+                        self.fault(world);
+                        break;
+                    }
+                },
+                TankState::Dead | TankState::Halted => break,
                 TankState::Pending(_) => {
                     let mut newstate = TankState::Free;
                     core::mem::swap(&mut self.state, &mut newstate);
@@ -151,54 +651,141 @@ impl Entity for Tank {
                     }
                 }
             }
-            match timer(&uc, self.instrs_per_step) {
+            // Any upcall at all, yield included, shows the program is still responsive
+            acted = true;
+            match timer(&uc, &world.config) {
                 None => (),
-                Some((idx, maxtime)) => {
-                    if self.timers[idx] >= self.instrs_per_step {
+                Some((idx, cooldown)) => {
+                    if self.timers[idx] > 0 {
+                        // Fire reports the cooldown instead of waiting it out
+                        match uc {
+                            Upcall::Fire(ref rv) | Upcall::FireKind(_, ref rv) => {
+                                *rv.lock().unwrap() = Some(FireStatus::Cooldown as i32);
+                                world.record_upcall(self.id, &uc);
+                                continue;
+                            }
+                            _ => (),
+                        }
+                        // Scan gives back what it saw last time, stale as it may be
+                        match uc {
+                            Upcall::Scan(_, _, ref rv) => {
+                                *rv.lock().unwrap() = Some(self.scan_cache.unwrap_or(0));
+                                world.record_upcall(self.id, &uc);
+                                continue;
+                            }
+                            Upcall::ScanInto(_, _, ptr) => {
+                                world.record_upcall(self.id, &uc);
+                                self.write_scan(world, ptr, self.scan_cache.unwrap_or(0));
+                                continue;
+                            }
+                            _ => (),
+                        }
                         self.state = TankState::Pending(uc);
                         break;
-                    } else {
-                        let counter = isize::max(self.timers[idx] as isize, self.vm.counter());
-                        self.vm.set_counter(counter);
-                        self.timers[idx] = self.vm.counter() as usize + maxtime;
                     }
+                    self.timers[idx] = cooldown;
+                }
+            }
+            if world.config.upcall_trace_len > 0 {
+                if self.trace.len() >= world.config.upcall_trace_len {
+                    self.trace.pop_front();
                 }
+                self.trace.push_back((world.steps, uc.clone()));
             }
+            // The record shares the upcall's return slot, so it sees the value filled in below
+            world.record_upcall(self.id, &uc);
             match uc {
                 Upcall::Scan(hl, hu, rv) => {
+                    *rv.lock().unwrap() = Some(self.scan(world, hl, hu));
+                }
+                Upcall::ScanInto(hl, hu, ptr) => {
+                    let result = self.scan(world, hl, hu);
+                    self.write_scan(world, ptr, result);
+                }
+                Upcall::ScanBullets(hl, hu, rv) => {
+                    let (hl, hu) = (world.config.angle_unit.to_radians(hl), world.config.angle_unit.to_radians(hu));
+                    let bounds = if hl < hu { (hl, hu) } else { (hu, hl) };
+                    *rv.lock().unwrap() = Some(world.scan_bullets(self.pos, bounds) as i32);
+                }
+                Upcall::ScanHottest(hl, hu, rv) => {
+                    let (hl, hu) = (world.config.angle_unit.to_radians(hl), world.config.angle_unit.to_radians(hu));
                     let bounds = if hl < hu { (hl, hu) } else { (hu, hl) };
-                    let (us, them) = world.scan(self.pos, self.team, bounds);
-                    *rv.lock().unwrap() = Some(((us as u64) << 32) | them as u64);
-                }
-                Upcall::Fire => {
-                    self.apply_heat(world.config.shoot_heat);
-                    world
-                        .bullets
-                        .write()
-                        .unwrap()
-                        .push(Identity(Arc::new(RwLock::new(Bullet {
-                            pos: self.pos + Pair::polar(self.aim) * world.config.bullet_s,
-                            vel: Pair::polar(self.aim) * world.config.bullet_v,
-                            dead: false,
-                        }))));
+                    let bearing = world.scan_hottest(self.pos, self.team, bounds);
+                    let bearing = bearing.map(|a| world.config.angle_unit.from_radians(a));
+                    *rv.lock().unwrap() = Some(bearing.unwrap_or(-1.0));
                 }
+                Upcall::Fire(rv) => {
+                    let status = self.fire(world, BulletKind::Standard);
+                    *rv.lock().unwrap() = Some(status as i32);
+                }
+                Upcall::FireKind(kind, rv) => {
+                    let kind = BulletKind::from_i32(kind).unwrap_or(BulletKind::Standard);
+                    let status = self.fire(world, kind);
+                    *rv.lock().unwrap() = Some(status as i32);
+                }
+                // A NaN or infinite heading would poison positions, so those are ignored
                 Upcall::Aim(hd) => {
-                    self.aim = hd;
+                    if let Some(hd) = normalize_angle(world.config.angle_unit.to_radians(hd)) {
+                        self.aim = hd;
+                    }
                 }
                 Upcall::Turn(hd) => {
-                    self.angle = hd;
+                    if let Some(hd) = normalize_angle(world.config.angle_unit.to_radians(hd)) {
+                        self.angle = hd;
+                    }
                 }
                 Upcall::GPSX(rv) => {
-                    *rv.lock().unwrap() = Some(self.pos.x);
+                    let noise = self.sensor_noise(&world.config);
+                    *rv.lock().unwrap() = Some(self.pos.x + noise);
                 }
                 Upcall::GPSY(rv) => {
-                    *rv.lock().unwrap() = Some(self.pos.y);
+                    let noise = self.sensor_noise(&world.config);
+                    *rv.lock().unwrap() = Some(self.pos.y + noise);
                 }
                 Upcall::Temp(rv) => {
-                    *rv.lock().unwrap() = Some(self.temp);
+                    let noise = self.sensor_noise(&world.config);
+                    *rv.lock().unwrap() = Some((self.temp as f32 + noise).round() as i32);
+                }
+                Upcall::MyTeam(rv) => {
+                    *rv.lock().unwrap() = Some(self.team as i32);
+                }
+                Upcall::MyId(rv) => {
+                    *rv.lock().unwrap() = Some(self.id as i64);
+                }
+                Upcall::TeamCentroidX(rv) => {
+                    let centroid = world.centroid_with(self.team, Some(self.pos));
+                    *rv.lock().unwrap() = Some(centroid.map_or(core::f32::NAN, |c| c.x));
+                }
+                Upcall::TeamCentroidY(rv) => {
+                    let centroid = world.centroid_with(self.team, Some(self.pos));
+                    *rv.lock().unwrap() = Some(centroid.map_or(core::f32::NAN, |c| c.y));
+                }
+                // No team has an id out of Team's range, so those find nothing
+                Upcall::BearingToTeam(team, rv) => {
+                    let nearest = Team::try_from(team).ok().and_then(|team| world.nearest_on_team(self.pos, team));
+                    let bearing = nearest.map(|(_, a)| world.config.angle_unit.from_radians(a));
+                    *rv.lock().unwrap() = Some(bearing.unwrap_or(-1.0));
+                }
+                Upcall::DistToTeam(team, rv) => {
+                    let nearest = Team::try_from(team).ok().and_then(|team| world.nearest_on_team(self.pos, team));
+                    *rv.lock().unwrap() = Some(nearest.map_or(-1.0, |(d, _)| d));
+                }
+                Upcall::CooldownRemaining(rv) => {
+                    // Fire shares the world-altering timer
+                    *rv.lock().unwrap() = Some(self.timers[0] as i32);
+                }
+                Upcall::WasScanned(rv) => {
+                    *rv.lock().unwrap() = Some(self.scanned.min(i32::max_value() as usize) as i32);
                 }
                 Upcall::Forward => {
                     self.pos = self.pos + Pair::polar(self.angle) * world.config.tank_v;
+                    driven = true;
+                }
+                Upcall::SetThrottle(t) => {
+                    self.throttle = if t.is_nan() { 0.0 } else { t.max(-1.0).min(1.0) };
+                }
+                Upcall::SetLabel(s) => {
+                    self.label = Some(s);
                 }
                 Upcall::PostString(s) => {
                     println!("tank posted string: {}", s);
@@ -222,163 +809,803 @@ impl Entity for Tank {
                     println!("tank posted f64: {}", s);
                 },
                 Upcall::Explode => {
-                    println!("tank commiting suicide!");
-                    world.explode(self.pos, world.config.explode_rad);
+                    if world.config.explode_arm_delay > 0 {
+                        println!("tank arming!");
+                        self.arming = Some(world.config.explode_arm_delay);
+                    } else {
+                        println!("tank commiting suicide!");
+                        self.self_destruct(world, DeathCause::SelfDestruct);
+                    }
                     break;
                 }
                 Upcall::None => break,
             }
         }
+        // Throttle keeps the tank moving whether or not its program got to run this step, but
+        // doesn't add to a Forward; no tank covers more than `tank_v` in a step.
+        if self.throttle != 0.0 && !driven {
+            self.pos = self.pos + Pair::polar(self.angle) * (world.config.tank_v * self.throttle);
+        }
+        self.vel = self.pos + (-self.prev_pos);
+        self.apply_boundary(world);
         if self.temp >= world.config.death_heat {
             println!("tank too hot!");
-            world.explode(self.pos, world.config.explode_rad);
+            self.self_destruct(world, DeathCause::Overheat);
+        }
+        // A running tank that burns a whole step's budget without a single upcall is probably
+        // stuck in a loop, hogging it
+        if self.state == TankState::Free && exhausted && !acted {
+            self.idle_steps += 1;
+        } else {
+            self.idle_steps = 0;
+        }
+        if let Some(limit) = world.config.idle_timeout_steps {
+            if self.idle_steps >= limit {
+                println!("tank unresponsive!");
+                self.kill(DeathCause::Unresponsive);
+            }
         }
     }
 }
 
+// What `fire` returns to the tank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FireStatus {
+    Fired = 0,
+    Cooldown = 1,
+    TooHot = 2, // Firing would take the tank past `fire_heat_limit`
+    BulletCap = 3, // The tank already has `max_bullets_per_tank` bullets in the air
+    Jammed = 4, // Only happens above `jam_heat`
+    NoFireZone = 5, // The tank is inside a ZoneEffect::NoFire zone
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Bullet {
+    pub id: usize,
     pub pos: Pair,
+    #[serde(skip)]
+    pub prev_pos: Pair,
     pub vel: Pair,
     pub dead: bool,
+    // Steps, counting the one it was fired on, during which the bullet can't hit anything
+    // (including the tank that fired it or a neighbor standing at the muzzle).
+    #[serde(skip)]
+    pub immune_steps: usize,
+    #[serde(skip)]
+    pub layers: u32, // Inherited from the tank that fired it
+    pub owner: Option<usize>, // Id of the tank that fired it
+    pub age: usize, // Steps the bullet has been in flight
+    pub kind: BulletKind,
+}
+
+// What a bullet does, chosen when it's fired; the details of each are in Configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulletKind {
+    Standard = 0,
+    Heavy = 1, // Slower, bigger, and more damaging
+    Scatter = 2, // Splits into a fan of standard bullets after `scatter_age` steps
+}
+
+impl BulletKind {
+    pub fn from_i32(kind: i32) -> Option<BulletKind> {
+        match kind {
+            0 => Some(BulletKind::Standard),
+            1 => Some(BulletKind::Heavy),
+            2 => Some(BulletKind::Scatter),
+            _ => None,
+        }
+    }
+}
+
+impl Bullet {
+    pub fn new(pos: Pair, vel: Pair) -> Bullet {
+        Bullet {
+            id: 0,
+            pos,
+            prev_pos: pos,
+            vel,
+            dead: false,
+            immune_steps: 1,
+            layers: !0,
+            owner: None,
+            age: 0,
+            kind: BulletKind::Standard,
+        }
+    }
 }
 
 impl Entity for Bullet {
-    fn step(&mut self, _world: &World) {
+    fn step(&mut self, world: &World) {
+        self.prev_pos = self.pos;
+        self.vel = (self.vel + world.config.gravity) * (1.0 - world.config.drag);
+        let speed = self.vel.mag();
+        if speed > world.config.max_bullet_v {
+            self.vel = self.vel * (world.config.max_bullet_v / speed);
+        }
         self.pos = self.pos + self.vel;
+        self.age += 1;
+    }
+}
+
+// An area of the arena with its own rules, applying to tanks whose centers are inside it. Unlike
+// obstacles, zones don't block anything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Zone {
+    pub shape: ZoneShape,
+    pub effect: ZoneEffect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase")]
+pub enum ZoneShape {
+    Rect(AABB),
+    Circle { center: Pair, radius: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ZoneEffect {
+    NoFire, // `fire` does nothing, returning FireStatus::NoFireZone
+    Heal { rate: i32 }, // Heat taken off every step, on top of idle heat
+}
+
+impl Zone {
+    pub fn contains(&self, p: Pair) -> bool {
+        match self.shape {
+            ZoneShape::Rect(ref aabb) => aabb.contains(p),
+            ZoneShape::Circle { center, radius } => {
+                let d = p + (-center);
+                d.dot(&d) < radius * radius
+            }
+        }
+    }
+}
+
+// Whether tanks or bullets move first in a step. Under TanksFirst, a bullet fired this step also
+// moves this step, so it's first seen one step's travel past the muzzle; under BulletsFirst it's
+// first seen at the muzzle, and tanks react to where bullets are rather than where they were.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOrder {
+    TanksFirst,
+    BulletsFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundaryBehavior {
+    Ignore,
+    Clamp, // Stop at the edge
+    Kill,
+    Bounce, // Reflect off the edge, like a billiard ball
+}
+
+// How tank programs express angles to upcalls; the world itself always works in radians.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+    Radians,
+    Degrees,
+}
+
+impl AngleUnit {
+    pub fn to_radians(self, a: f32) -> f32 {
+        match self {
+            AngleUnit::Radians => a,
+            AngleUnit::Degrees => a.to_radians(),
+        }
+    }
+
+    pub fn from_radians(self, a: f32) -> f32 {
+        match self {
+            AngleUnit::Radians => a,
+            AngleUnit::Degrees => a.to_degrees(),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Configuration {
-    pub shoot_heat: i32,
+    pub shoot_heat: i32, // Heat of every shot, before `heat_per_bullet_speed`
+    pub heat_per_bullet_speed: f32, // Extra heat per unit of muzzle speed, so faster shots run hotter
     pub idle_heat: i32,
     pub move_heat: i32,
     pub death_heat: i32,
+    // From step `sudden_death_after` on, idle heat goes up by `sudden_death_heat_ramp` every step,
+    // until every tank has cooked and the match is over.
+    pub sudden_death_after: Option<usize>,
+    pub sudden_death_heat_ramp: i32,
     pub instrs_per_step: usize,
+    pub init_instrs: usize, // Instructions a tank may run while being built, before its first step
+    pub max_wasm_memory_pages: Option<usize>, // Largest linear memory (in 64KiB pages) a tank may have
+    // When set, this many instructions per step are shared evenly between the running tanks,
+    // overriding their own `instrs_per_step`, so total compute per step stays bounded.
+    pub adaptive_budget: Option<usize>,
+    pub alter_cooldown: usize, // Steps between world-altering upcalls
+    pub idle_timeout_steps: Option<usize>, // Kill tanks that run this many steps without an upcall
+    pub scan_cooldown: usize, // Steps between scans that actually look; 0 doesn't limit them
+    pub fire_heat_limit: Option<i32>, // Refuse shots that would take a tank's temperature past this
+    pub max_bullets_per_tank: Option<usize>,
+    pub max_total_bullets: Option<usize>, // Past this, the oldest bullets in the world are removed
+    pub jam_heat: Option<i32>, // Temperature above which guns may jam
+    pub jam_chance: f32, // Probability a shot jams when above `jam_heat`
+    pub jam_spread: f32, // Extra spread (radians) for shots fired above `jam_heat`
     pub bullet_v: f32,
-    pub bullet_s: f32,
-    pub hit_rad: f32,
+    // No bullet ever goes faster, whatever its kind, even once gravity has had its way; past this
+    // collisions start getting missed
+    pub max_bullet_v: f32,
+    pub bullet_s: f32, // Muzzle offset: bullets spawn this far ahead of the tank along `aim`
+    pub bullet_spawn_immunity: usize, // Steps a new bullet can't hit anything; see Bullet::immune_steps
+    pub aim_assist_tolerance: Option<f32>, // Shots snap to an enemy within this many radians of `aim`
+    pub bullet_spread: f32, // Maximum deviation (radians) of a bullet's heading from `aim`
+    pub gravity: Pair, // Added to every bullet's velocity each step
+    pub drag: f32, // Fraction of a bullet's velocity lost each step
+    pub sensor_noise: f32, // Standard deviation of the noise added to GPS and temperature reads
+    pub tank_radius: f32,
+    pub bullet_radius: f32,
+    pub heavy_speed: f32, // Fraction of `bullet_v` heavy bullets fly at
+    pub heavy_radius: f32,
+    pub heavy_damage: i32, // Replaces `bullet_damage` for heavy bullets
+    pub scatter_age: usize, // Steps in flight before a scatter bullet splits
+    pub scatter_count: usize, // Bullets a scatter bullet splits into
+    pub scatter_spread: f32, // Radians either side of its heading the pieces fan out over
     pub tank_v: f32,
     pub explode_rad: f32,
+    pub explode_damage: Option<i32>, // Damage at the center of a blast; None kills outright
+    pub explode_arm_delay: usize, // Steps between a tank asking to explode and the blast
+    pub world_bound: AABB,
+    pub obstacles: Vec<AABB>, // Solid boxes tanks can't drive into
+    pub zones: Vec<Zone>,
+    pub step_order: StepOrder,
+    pub boundary: BoundaryBehavior, // What happens to tanks that leave `world_bound`
+    pub angle_unit: AngleUnit, // For angles going into and out of upcalls
+    pub swept_scan: bool, // Whether scans also catch what the tank turned past this step
+    pub scan_range: Option<f32>, // Tanks farther than this are invisible to scans
+    pub seed: u64,
+    pub max_tanks: Option<usize>, // Live tanks the world will hold; each has its own wasm instance
+    pub despawn_grace: Option<usize>, // Steps a dead tank lingers before removal; None keeps them
+    pub spawn_protection: usize, // Steps after being added during which a tank can't be hurt
+    pub import_namespace: String, // Module name tank programs import host calls from
+    pub teams: HashMap<Team, TeamMeta>,
+    pub team_starts: HashMap<Team, TeamStart>,
+    pub collision: CollisionRule, // What happens to entities that collide
+    pub tank_collisions: bool, // Whether tanks can run into each other, or only into bullets
+    pub friendly_collision: bool, // Whether teammates can run into each other, or pass through
+    pub bullet_collisions: bool, // Whether bullets can hit each other
+    pub hit_requires_closing: bool, // Whether a bullet must be approaching a tank to hit it
+    pub tank_health: i32,
+    pub bullet_damage: i32, // Health a tank loses per bullet, under CollisionRule::Damage
+    pub ram_damage: i32, // Health each tank loses when two collide, under CollisionRule::Damage
+    pub quadtree_threshold: usize, // Entity count from which collisions go through a quadtree
+    pub default_layers: u32, // Collision layer mask tanks get unless built with another
+    pub record_upcalls: bool, // Log every upcall and its result, for World::replay_upcalls
+    pub max_label_len: usize, // In bytes
+    pub upcall_trace_len: usize, // Upcalls each tank remembers for debugging; 0 disables tracing
+    pub trace_team: Option<Team>, // Team whose traces are included in broadcasts
 }
 
 impl Default for Configuration {
     fn default() -> Configuration {
         Configuration {
             shoot_heat: 26,
+            heat_per_bullet_speed: 0.0,
             idle_heat: -2,
             move_heat: -2,
             death_heat: 300,
+            sudden_death_after: None,
+            sudden_death_heat_ramp: 1,
             instrs_per_step: 30,
+            init_instrs: 0,
+            max_wasm_memory_pages: None,
+            adaptive_budget: None,
+            alter_cooldown: 1,
+            idle_timeout_steps: None,
+            scan_cooldown: 0,
+            fire_heat_limit: None,
+            max_bullets_per_tank: None,
+            max_total_bullets: None,
+            jam_heat: None,
+            jam_chance: 0.25,
+            jam_spread: 0.1,
             bullet_v: 5.0,
+            max_bullet_v: 20.0,
             bullet_s: 30.0,
-            hit_rad: 10.0,
+            bullet_spawn_immunity: 1,
+            aim_assist_tolerance: None,
+            bullet_spread: 0.0,
+            gravity: Pair::zero(),
+            drag: 0.0,
+            sensor_noise: 0.0,
+            tank_radius: 5.0,
+            bullet_radius: 1.0,
+            heavy_speed: 0.5,
+            heavy_radius: 3.0,
+            heavy_damage: 60,
+            scatter_age: 15,
+            scatter_count: 5,
+            scatter_spread: 0.4,
             tank_v: 1.0,
             explode_rad: 50.0,
+            explode_damage: None,
+            explode_arm_delay: 0,
+            world_bound: AABB::around(Pair::zero(), Pair::both(1000.0)),
+            obstacles: Vec::new(),
+            zones: Vec::new(),
+            step_order: StepOrder::TanksFirst,
+            boundary: BoundaryBehavior::Ignore,
+            angle_unit: AngleUnit::Radians,
+            swept_scan: false,
+            scan_range: None,
+            seed: 0,
+            max_tanks: None,
+            despawn_grace: None,
+            spawn_protection: 0,
+            import_namespace: "env".into(),
+            teams: HashMap::new(),
+            team_starts: HashMap::new(),
+            collision: CollisionRule::Lethal,
+            tank_collisions: true,
+            friendly_collision: true,
+            bullet_collisions: false,
+            hit_requires_closing: false,
+            tank_health: 100,
+            bullet_damage: 25,
+            ram_damage: 50,
+            quadtree_threshold: 16,
+            default_layers: 1,
+            record_upcalls: false,
+            max_label_len: 32,
+            upcall_trace_len: 0,
+            trace_team: None,
         }
     }
 }
 
 impl Configuration {
+    pub fn bullet_radius_of(&self, kind: BulletKind) -> f32 {
+        match kind {
+            BulletKind::Heavy => self.heavy_radius,
+            _ => self.bullet_radius,
+        }
+    }
+
+    pub fn bullet_damage_of(&self, kind: BulletKind) -> i32 {
+        match kind {
+            BulletKind::Heavy => self.heavy_damage,
+            _ => self.bullet_damage,
+        }
+    }
+
+    // The biggest any bullet can be.
+    pub fn max_bullet_radius(&self) -> f32 {
+        self.bullet_radius.max(self.heavy_radius)
+    }
+
+    // The speed a bullet of `kind` leaves the muzzle at.
+    pub fn bullet_v_of(&self, kind: BulletKind) -> f32 {
+        match kind {
+            BulletKind::Heavy => self.bullet_v * self.heavy_speed,
+            _ => self.bullet_v,
+        }
+        .min(self.max_bullet_v)
+    }
+
+    // The heat a tank takes from firing one bullet of `kind`.
+    pub fn shot_heat(&self, kind: BulletKind) -> i32 {
+        self.shoot_heat
+            .saturating_add((self.heat_per_bullet_speed * self.bullet_v_of(kind)).round() as i32)
+    }
+
+    // Check for settings the simulation can't cope with; see try_build.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let fastest = self.bullet_v.max(self.bullet_v * self.heavy_speed);
+        if !(fastest <= self.max_bullet_v) {
+            return Err(ConfigError::BulletTooFast {
+                speed: fastest,
+                max: self.max_bullet_v,
+            });
+        }
+        Ok(())
+    }
+
+    // As try_build, but panics on an invalid configuration.
     pub fn build(self) -> World {
-        World {
-            config: self,
+        match self.try_build() {
+            Ok(world) => world,
+            Err(e) => panic!("Invalid configuration: {}", e),
+        }
+    }
+
+    pub fn try_build(self) -> Result<World, ConfigError> {
+        self.validate()?;
+        Ok(World {
+            config: Arc::new(self),
             tanks: Arc::new(RwLock::new(Vec::new())),
             bullets: Arc::new(RwLock::new(Vec::new())),
             action_queue: RefCell::new(Vec::new()),
-        }
+            effects: RefCell::new(Vec::new()),
+            events: RefCell::new(Vec::new()),
+            event_tx: None,
+            upcall_log: RefCell::new(Vec::new()),
+            call_stats: Arc::new(Mutex::new(CallStats::default())),
+            tank_budget: None,
+            steps: 0,
+            next_id: 0,
+            next_bullet_id: Cell::new(0),
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct World {
-    pub config: Configuration,
+    pub config: Arc<Configuration>, // Fixed once the world is built; tanks share it
     pub tanks: Arc<RwLock<Vec<Identity<Arc<RwLock<Tank>>>>>>,
     pub bullets: Arc<RwLock<Vec<Identity<Arc<RwLock<Bullet>>>>>>,
     action_queue: RefCell<Vec<WorldAction>>,
+    effects: RefCell<Vec<Effect>>,
+    events: RefCell<Vec<GameEvent>>,
+    event_tx: Option<mpsc::Sender<GameEvent>>,
+    upcall_log: RefCell<Vec<UpcallRecord>>,
+    call_stats: Arc<Mutex<CallStats>>,
+    tank_budget: Option<usize>,
+    pub steps: usize,
+    next_id: usize,
+    next_bullet_id: Cell<usize>,
 }
 
-#[derive(Clone, Debug)]
-enum WorldAction {
-    Explode(Pair, f32), // Center and radius of explosion
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpawnError {
+    OutOfBounds(Pair),
+    TooManyTanks(usize), // The world already has `max_tanks` live tanks
 }
 
-impl World {
-    pub fn add_tank(&mut self, tank: Tank) {
-        self.tanks
-            .write()
-            .unwrap()
-            .push(Identity(Arc::new(RwLock::new(tank))));
+impl core::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SpawnError::OutOfBounds(p) => write!(f, "spawn point ({}, {}) is outside the world", p.x, p.y),
+            SpawnError::TooManyTanks(max) => write!(f, "the world already has its maximum of {} live tanks", max),
+        }
     }
+}
 
-    pub fn step(&mut self) {
-        // All entity steps
-        for t in self.tanks.read().unwrap().iter() {
-            if t.read().unwrap().state != TankState::Dead {
-                t.write().unwrap().step(&self);
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    BulletTooFast { speed: f32, max: f32 }, // Some kind of bullet would go faster than `max_bullet_v`
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigError::BulletTooFast { speed, max } => {
+                write!(f, "bullets could go {} per step, over the maximum of {}", speed, max)
             }
         }
-        for b in self.bullets.read().unwrap().iter() {
-            b.write().unwrap().step(&self);
-        }
+    }
+}
 
-        // All collisions
-        enum EntityRef {
-            Tank(Arc<RwLock<Tank>>),
-            Bullet(Arc<RwLock<Bullet>>),
-        }
-        let mut root: QuadTreeNode<EntityRef> = QuadTreeBuilder::from_bound(AABB::over_points(
-            self.tanks
+// An entity in the collision broad phase, by its index in the tank or bullet list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Tank(usize),
+    Bullet(usize),
+}
+
+// Transient visual events from a single step, for clients to animate; not part of the state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Effect {
+    Explosion { pos: Pair, rad: f32 },
+    Impact { pos: Pair },
+}
+
+// A summary of the match so far, for operators to poll without spectating; see World::status.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStatus {
+    pub step: usize,
+    pub living: BTreeMap<Team, usize>, // Live tanks per team, including teams with none left
+    pub tanks: usize, // All tanks still in the world, dead or alive
+    pub bullets: usize,
+    pub finished: bool,
+}
+
+// Things that happened during a step, for embedders to react to; see World::subscribe.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum GameEvent {
+    Shot { tank: usize, team: Team, bullet: usize },
+    Death { tank: usize, team: Team },
+    MatchEnd { step: usize },
+}
+
+#[derive(Clone, Debug)]
+enum WorldAction {
+    Explode(Pair, f32, Option<usize>), // Center and radius of explosion, and the tank that went off
+}
+
+impl World {
+    // Tanks are given ids in the order they're added; ids are never reused, even once the tank
+    // has been despawned. A tank can't be placed outside the world bound (its edges are fine).
+    pub fn add_tank(&mut self, mut tank: Tank) -> Result<usize, SpawnError> {
+        if tank.pos.clamp_to(&self.config.world_bound) != tank.pos {
+            return Err(SpawnError::OutOfBounds(tank.pos));
+        }
+        if let Some(max) = self.config.max_tanks {
+            let live = self
+                .tanks
                 .read()
                 .unwrap()
                 .iter()
-                .map(|t| t.read().unwrap().pos)
-                .chain(
-                    self.bullets
-                        .read()
-                        .unwrap()
-                        .iter()
-                        .map(|b| b.read().unwrap().pos),
-                ),
-        ))
-        .build();
+                .filter(|t| t.read().unwrap().state != TankState::Dead)
+                .count();
+            if live >= max {
+                return Err(SpawnError::TooManyTanks(max));
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        tank.id = id;
+        // One stream per tank, so teammates (copies of one program, say) don't share their luck
+        tank.rng = Rng::stream(self.config.seed, id as u64);
+        tank.protection = self.config.spawn_protection;
+        if let Some(vm) = &mut tank.vm {
+            vm.set_call_stats(self.call_stats.clone());
+        }
+        self.tanks
+            .write()
+            .unwrap()
+            .push(Identity(Arc::new(RwLock::new(tank))));
+        Ok(id)
+    }
 
-        for t in self.tanks.read().unwrap().iter() {
-            core::mem::drop(root.add_pt((t.read().unwrap().pos, EntityRef::Tank(Arc::clone(t)))));
+    pub fn add_bullet(&self, mut bullet: Bullet) -> usize {
+        let id = self.next_bullet_id.get();
+        self.next_bullet_id.set(id + 1);
+        bullet.id = id;
+        self.bullets
+            .write()
+            .unwrap()
+            .push(Identity(Arc::new(RwLock::new(bullet))));
+        id
+    }
+
+    // Receive every GameEvent from now on. Events are sent at the end of each step, once the
+    // world's locks are released; there is only one subscriber, so this replaces any earlier one.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<GameEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.event_tx = Some(tx);
+        rx
+    }
+
+    // Return the world to how Configuration::build left it, so it can host another match. The
+    // containers keep their allocations, but every tank and bullet is dropped, and ids start
+    // again from 0. The config and any subscriber are kept.
+    pub fn reset(&mut self) {
+        self.tanks.write().unwrap().clear();
+        self.bullets.write().unwrap().clear();
+        self.action_queue.get_mut().clear();
+        self.effects.get_mut().clear();
+        self.events.get_mut().clear();
+        self.upcall_log.get_mut().clear();
+        *self.call_stats.lock().unwrap() = CallStats::default();
+        self.tank_budget = None;
+        self.steps = 0;
+        self.next_id = 0;
+        self.next_bullet_id.set(0);
+    }
+
+    // Host calls made by all of this world's tanks so far, by kind.
+    pub fn call_stats(&self) -> CallStats {
+        self.call_stats.lock().unwrap().clone()
+    }
+
+    // Run `f` over the tanks under a single read lock, so it sees one consistent list. A lock
+    // poisoned by a panic elsewhere is read anyway, since the list is only ever replaced whole.
+    pub fn with_tanks<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[Identity<Arc<RwLock<Tank>>>]) -> R,
+    {
+        let tanks = self.tanks.read().unwrap_or_else(|e| e.into_inner());
+        f(&tanks)
+    }
+
+    // As with_tanks, for bullets.
+    pub fn with_bullets<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&[Identity<Arc<RwLock<Bullet>>>]) -> R,
+    {
+        let bullets = self.bullets.read().unwrap_or_else(|e| e.into_inner());
+        f(&bullets)
+    }
+
+    // The heat every tank takes each step, just for existing; see `sudden_death_after`.
+    pub fn idle_heat(&self) -> i32 {
+        match self.config.sudden_death_after {
+            Some(start) if self.steps >= start => {
+                let ramp_steps = (self.steps - start + 1).min(i32::max_value() as usize) as i32;
+                self.config
+                    .idle_heat
+                    .saturating_add(self.config.sudden_death_heat_ramp.saturating_mul(ramp_steps))
+            }
+            _ => self.config.idle_heat,
         }
-        for b in self.bullets.read().unwrap().iter() {
-            core::mem::drop(root.add_pt((b.read().unwrap().pos, EntityRef::Bullet(Arc::clone(b)))));
+    }
+
+    pub fn in_sudden_death(&self) -> bool {
+        self.config.sudden_death_after.map_or(false, |start| self.steps >= start)
+    }
+
+    // The play area; tanks can't be spawned outside it, and bullets never spawn outside it.
+    pub fn arena_bounds(&self) -> AABB {
+        self.config.world_bound.clone()
+    }
+
+    // The first live tank on `team`, if it has one.
+    pub fn tank_by_team(&self, team: Team) -> Option<Identity<Arc<RwLock<Tank>>>> {
+        self.tanks
+            .read()
+            .unwrap()
+            .iter()
+            .find(|t| {
+                let t = t.read().unwrap();
+                t.team == team && t.state != TankState::Dead
+            })
+            .cloned()
+    }
+
+    // Every tank on `team`, living or dead, for when a team fields more than one.
+    pub fn tanks_by_team(&self, team: Team) -> Vec<Identity<Arc<RwLock<Tank>>>> {
+        self.tanks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|t| t.read().unwrap().team == team)
+            .cloned()
+            .collect()
+    }
+
+    // Instructions each tank gets this step under `adaptive_budget`, if it's enabled.
+    pub fn tank_budget(&self) -> Option<usize> {
+        self.tank_budget
+    }
+
+    // Stepping is serial and deterministic: tanks act in the order they were added, bullets move
+    // in the order they were fired, and nothing else (collisions, explosions, eviction, despawning)
+    // may depend on the order entities happen to be stored in. The same world stepped twice
+    // always ends up in the same state; see assert_deterministic_step.
+    pub fn step(&mut self) {
+        self.step_filtered(None);
+    }
+
+    // A copy of this world with its own tanks and bullets, stored in reverse order, or None if
+    // any tank has a program (a running VM can't be copied). Events aren't forwarded from it.
+    pub fn fork_reversed(&self) -> Option<World> {
+        let mut tanks = Vec::new();
+        for t in self.tanks.read().unwrap().iter().rev() {
+            let t = t.read().unwrap();
+            if t.vm.is_some() {
+                return None;
+            }
+            tanks.push(Identity(Arc::new(RwLock::new(t.clone()))));
         }
+        let bullets = self
+            .bullets
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|b| Identity(Arc::new(RwLock::new(b.read().unwrap().clone()))))
+            .collect();
+        let mut fork = self.clone();
+        fork.tanks = Arc::new(RwLock::new(tanks));
+        fork.bullets = Arc::new(RwLock::new(bullets));
+        fork.call_stats = Arc::new(Mutex::new(self.call_stats()));
+        fork.event_tx = None;
+        Some(fork)
+    }
 
-        for t in self.tanks.read().unwrap().iter() {
-            let v: Vec<&EntityRef> = root
-                .query(AABB::around(
-                    t.read().unwrap().pos,
-                    Pair::both(self.config.hit_rad),
-                ))
-                .map(|(_, r)| r)
+    // Step this world alongside a copy of it with everything stored in reverse order, and panic
+    // if the two disagree afterward; a disagreement means some part of the step depends on
+    // storage order. Meant for tests, and only for worlds without programs: a running VM can't
+    // be copied, so a world where any tank has one is refused with a panic rather than passed
+    // unchecked.
+    pub fn assert_deterministic_step(&mut self) {
+        self.assert_deterministic_with(World::step);
+    }
+
+    // As assert_deterministic_step, advancing both worlds with `step` instead.
+    pub fn assert_deterministic_with<F: FnMut(&mut World)>(&mut self, mut step: F) {
+        let mut fork = self
+            .fork_reversed()
+            .expect("only worlds without programs can be checked for order dependence");
+        step(self);
+        step(&mut fork);
+        let (ours, theirs) = (self.state_hash(), fork.state_hash());
+        assert_eq!(
+            ours, theirs,
+            "step {} depends on entity order: {:#x} stepped in order, {:#x} reversed",
+            self.steps, ours, theirs
+        );
+    }
+
+    // Step the world, but only run the tanks on `teams`; the rest stay frozen where they are.
+    // Bullets and collisions are processed for everyone as usual.
+    pub fn step_teams(&mut self, teams: &[Team]) {
+        self.step_filtered(Some(teams));
+    }
+
+    fn step_filtered(&mut self, teams: Option<&[Team]>) {
+        self.effects.borrow_mut().clear();
+        self.events.borrow_mut().clear();
+        let was_finished = self.finished();
+        let (living, running) = self.with_tanks(|tanks| {
+            let living: Vec<usize> = tanks
+                .iter()
+                .map(|t| t.read().unwrap())
+                .filter(|t| t.state != TankState::Dead)
+                .map(|t| t.id)
                 .collect();
-            if v.iter()
-                .filter(|r| match r {
-                    &EntityRef::Tank(ref t) => t.read().unwrap().state != TankState::Dead,
-                    &EntityRef::Bullet(ref b) => !b.read().unwrap().dead,
+            let running = tanks
+                .iter()
+                .filter(|t| match t.read().unwrap().state {
+                    TankState::Dead | TankState::Halted => false,
+                    _ => true,
                 })
-                .any(|_| true)
-            {
-                for r in v {
-                    match r {
-                        &EntityRef::Tank(ref t) => t.write().unwrap().state = TankState::Dead,
-                        &EntityRef::Bullet(ref b) => b.write().unwrap().dead = true,
+                .count();
+            (living, running)
+        });
+        self.tank_budget = self
+            .config
+            .adaptive_budget
+            .map(|total| usize::max(1, total / usize::max(1, running)));
+
+        // All entity steps
+        match self.config.step_order {
+            StepOrder::TanksFirst => {
+                self.step_tanks(teams);
+                self.step_bullets();
+            }
+            StepOrder::BulletsFirst => {
+                self.step_bullets();
+                self.step_tanks(teams);
+            }
+        }
+        if let Some(cap) = self.config.max_total_bullets {
+            self.evict_bullets(cap);
+        }
+
+        // Tanks that drove into an obstacle end up flush against it
+        if !self.config.obstacles.is_empty() {
+            for t in self.tanks.read().unwrap().iter() {
+                let mut tank = t.write().unwrap();
+                for obstacle in self.config.obstacles.iter() {
+                    if let Some(mtv) = obstacle.push_out(tank.pos, self.config.tank_radius) {
+                        tank.pos = tank.pos + mtv;
                     }
                 }
             }
         }
 
+        // All collisions. With no bullets about and tanks unable to hit each other, nothing can
+        // collide, so the search is skipped entirely.
+        let live_bullets = self
+            .bullets
+            .read()
+            .unwrap()
+            .iter()
+            .any(|b| !b.read().unwrap().dead);
+        if live_bullets || self.config.tank_collisions {
+            self.collide();
+        }
+        // Immunity counts collision phases, so it lasts the same however entities are ordered
+        for b in self.bullets.read().unwrap().iter() {
+            let mut b = b.write().unwrap();
+            b.immune_steps = b.immune_steps.saturating_sub(1);
+        }
+
+        // Actions apply in the order they were queued, which is tank order within a step; that
+        // keeps the outcome reproducible when one explosion's victims matter to the next.
         let mut queue = self.action_queue.borrow_mut();
-        while let Some(action) = queue.pop() {
+        for action in queue.drain(..) {
             match action {
-                WorldAction::Explode(pos, rad) => self.do_explode(pos, rad),
+                WorldAction::Explode(pos, rad, source) => self.do_explode(pos, rad, source),
             }
         }
 
@@ -392,6 +1619,332 @@ impl World {
             .cloned()
             .collect();
         *self.bullets.write().unwrap() = bullets;
+
+        for t in self.tanks.read().unwrap().iter() {
+            let t = t.read().unwrap();
+            if t.state == TankState::Dead && living.contains(&t.id) {
+                self.events.borrow_mut().push(GameEvent::Death {
+                    tank: t.id,
+                    team: t.team,
+                });
+            }
+        }
+        if !was_finished && self.finished() {
+            self.events
+                .borrow_mut()
+                .push(GameEvent::MatchEnd { step: self.steps });
+        }
+
+        // Scans are reported the step after they happen, so it doesn't matter whether the scanner
+        // stepped before or after its target
+        for t in self.tanks.read().unwrap().iter() {
+            let mut tank = t.write().unwrap();
+            tank.protection = tank.protection.saturating_sub(1);
+            tank.scanned = tank.scans;
+            tank.scans = 0;
+        }
+
+        self.despawn_dead_tanks();
+        self.steps += 1;
+
+        if let Some(ref tx) = self.event_tx {
+            let gone = self
+                .events
+                .borrow()
+                .iter()
+                .any(|e| tx.send(e.clone()).is_err());
+            if gone {
+                self.event_tx = None;
+            }
+        }
+    }
+
+    // Find everything that collided this step and hand it to the collision rule.
+    fn collide(&self) {
+        let pairs = self.detect_collisions();
+        self.config.collision.resolver().resolve(self, &pairs);
+    }
+
+    // Every pair of entities whose paths crossed during the last step, without doing anything
+    // about it. Each pair is reported once; tanks come before bullets.
+    pub fn detect_collisions(&self) -> Vec<Collision> {
+        let tanks = self.tanks.read().unwrap_or_else(|e| e.into_inner());
+        let bullets = self.bullets.read().unwrap_or_else(|e| e.into_inner());
+        // Entities are found by index, and only the ones that actually collide are cloned out;
+        // cloning every Arc every step costs more than it looks.
+        let entity = |slot: Slot| match slot {
+            Slot::Tank(i) => EntityRef::Tank(Arc::clone(&tanks[i])),
+            Slot::Bullet(i) => EntityRef::Bullet(Arc::clone(&bullets[i])),
+        };
+
+        // Collisions are tested along each entity's whole path this step, not just where it ended
+        // up, so fast movers can't pass through each other between ticks. The broad phase has to
+        // allow for how far anything could have moved.
+        let mut max_move = 0.0f32;
+        let mut entities: Vec<(Pair, Slot)> = Vec::with_capacity(tanks.len() + bullets.len());
+        for (i, t) in tanks.iter().enumerate() {
+            let tank = t.read().unwrap();
+            max_move = max_move.max((tank.pos + (-tank.prev_pos)).mag());
+            entities.push((tank.pos, Slot::Tank(i)));
+        }
+        for (i, b) in bullets.iter().enumerate() {
+            let bullet = b.read().unwrap();
+            max_move = max_move.max((bullet.pos + (-bullet.prev_pos)).mag());
+            entities.push((bullet.pos, Slot::Bullet(i)));
+        }
+
+        // With only a handful of entities, building a tree costs more than checking every pair.
+        // Both paths feed the same exact test below, so they find the same collisions.
+        let root: Option<QuadTreeNode<Slot>> = if entities.len() >= self.config.quadtree_threshold {
+            // Bounds exclude their far edges, so pad the tree's a little to keep the outermost
+            // entities inside it.
+            let bound: AABB = entities.iter().map(|&(p, _)| p).collect();
+            Some(QuadTreeBuilder::from_bound(bound.grow(1.0)).build_from(entities.clone()))
+        } else {
+            None
+        };
+
+        // Entities are circles; the query box only needs to be big enough to hold anything that
+        // could be touching the tank, and the real test is on center distance.
+        let tank_r = self.config.tank_radius;
+        let bullet_r = self.config.max_bullet_radius();
+        let reach = tank_r + tank_r.max(bullet_r) + max_move;
+        let mut pairs: Vec<Collision> = Vec::new();
+        for (ti, t) in tanks.iter().enumerate() {
+            let (id, team, prev, pos, vel, layers) = {
+                let tank = t.read().unwrap();
+                if tank.state == TankState::Dead {
+                    continue;
+                }
+                (tank.id, tank.team, tank.prev_pos, tank.pos, tank.vel, tank.layers)
+            };
+            let candidates: Box<dyn Iterator<Item = Slot>> = match root {
+                Some(ref root) => Box::new(
+                    root.query(AABB::from_corners(prev, pos).grow(reach))
+                        .map(|(_, r)| *r),
+                ),
+                None => Box::new(entities.iter().map(|&(_, r)| r)),
+            };
+            // Tank-tank collisions are found from both sides; only keep one
+            let hits = candidates.filter(|&r| match r {
+                Slot::Tank(oi) => {
+                    if oi == ti {
+                        return false;
+                    }
+                    let o = tanks[oi].read().unwrap();
+                    self.config.tank_collisions
+                        && (self.config.friendly_collision || o.team != team)
+                        && o.id > id
+                        && o.state != TankState::Dead
+                        && o.layers & layers != 0
+                        && closest_approach(prev, pos, o.prev_pos, o.pos) < 2.0 * tank_r
+                }
+                Slot::Bullet(bi) => {
+                    let b = bullets[bi].read().unwrap();
+                    // Optionally, a bullet only hits if it was coming at the tank when the
+                    // step began, so one keeping pace with it or drifting away doesn't
+                    let closing = !self.config.hit_requires_closing
+                        || (b.vel + (-vel)).dot(&(prev + (-b.prev_pos))) > 0.0;
+                    !b.dead
+                        && b.immune_steps == 0
+                        && b.layers & layers != 0
+                        && closing
+                        && closest_approach(prev, pos, b.prev_pos, b.pos)
+                            < tank_r + self.config.bullet_radius_of(b.kind)
+                }
+            });
+            pairs.extend(hits.map(|r| (entity(Slot::Tank(ti)), entity(r))));
+        }
+
+        // Bullets only hit each other if `bullet_collisions` is set
+        if self.config.bullet_collisions {
+            let reach = 2.0 * bullet_r + max_move;
+            for (bi, b) in bullets.iter().enumerate() {
+                let (id, prev, pos, layers, radius) = {
+                    let bullet = b.read().unwrap();
+                    if bullet.dead || bullet.immune_steps > 0 {
+                        continue;
+                    }
+                    let radius = self.config.bullet_radius_of(bullet.kind);
+                    (bullet.id, bullet.prev_pos, bullet.pos, bullet.layers, radius)
+                };
+                let candidates: Box<dyn Iterator<Item = Slot>> = match root {
+                    Some(ref root) => Box::new(
+                        root.query(AABB::from_corners(prev, pos).grow(reach))
+                            .map(|(_, r)| *r),
+                    ),
+                    None => Box::new(entities.iter().map(|&(_, r)| r)),
+                };
+                let hits = candidates.filter(|&r| match r {
+                    Slot::Bullet(oi) => {
+                        if oi == bi {
+                            return false;
+                        }
+                        let o = bullets[oi].read().unwrap();
+                        o.id > id
+                            && !o.dead
+                            && o.immune_steps == 0
+                            && o.layers & layers != 0
+                            && closest_approach(prev, pos, o.prev_pos, o.pos)
+                                < radius + self.config.bullet_radius_of(o.kind)
+                    }
+                    Slot::Tank(_) => false,
+                });
+                pairs.extend(hits.map(|r| (entity(Slot::Bullet(bi)), entity(r))));
+            }
+        }
+        pairs
+    }
+
+    // Every tank and bullet whose center is in `area`, tanks first. With `live_only`, dead tanks
+    // and bullets are left out.
+    pub fn entities_in(&self, area: AABB, live_only: bool) -> Vec<EntityRef> {
+        let mut entities: Vec<(Pair, EntityRef)> = Vec::new();
+        for t in self.tanks.read().unwrap().iter() {
+            let tank = t.read().unwrap();
+            if !live_only || tank.state != TankState::Dead {
+                entities.push((tank.pos, EntityRef::Tank(Arc::clone(t))));
+            }
+        }
+        for b in self.bullets.read().unwrap().iter() {
+            let bullet = b.read().unwrap();
+            if !live_only || !bullet.dead {
+                entities.push((bullet.pos, EntityRef::Bullet(Arc::clone(b))));
+            }
+        }
+        if entities.is_empty() {
+            return Vec::new();
+        }
+        let bound: AABB = entities.iter().map(|&(p, _)| p).collect();
+        let root = QuadTreeBuilder::from_bound(bound.grow(1.0)).build_from(entities);
+        let mut found: Vec<EntityRef> = root.query(area).map(|(_, r)| r.clone()).collect();
+        found.sort_by_key(|r| match r {
+            EntityRef::Tank(_) => 0,
+            EntityRef::Bullet(_) => 1,
+        });
+        found
+    }
+
+    fn step_tanks(&self, teams: Option<&[Team]>) {
+        for t in self.tanks.read().unwrap().iter() {
+            if t.read().unwrap().state == TankState::Dead {
+                continue;
+            }
+            let mut tank = t.write().unwrap();
+            if teams.map_or(true, |teams| teams.contains(&tank.team)) {
+                tank.step(&self);
+            } else {
+                // A frozen tank didn't move this step, whatever it did in the last one
+                tank.prev_pos = tank.pos;
+                tank.prev_angle = tank.angle;
+            }
+        }
+    }
+
+    fn step_bullets(&self) {
+        for b in self.bullets.read().unwrap().iter() {
+            b.write().unwrap().step(&self);
+        }
+        self.split_scatter();
+    }
+
+    // Replace every scatter bullet that's old enough with a fan of standard ones, spread evenly
+    // about its heading and flying at its speed.
+    fn split_scatter(&self) {
+        let mut splitting = Vec::new();
+        for b in self.bullets.read().unwrap().iter() {
+            let mut b = b.write().unwrap();
+            if b.dead || b.kind != BulletKind::Scatter || b.age < self.config.scatter_age {
+                continue;
+            }
+            b.dead = true;
+            splitting.push(b.clone());
+        }
+        // Pieces take their ids in the order they're added, so that can't hang on the order the
+        // bullets happen to be stored in.
+        splitting.sort_by_key(|b| b.id);
+        let mut pieces = Vec::new();
+        for b in splitting {
+            let count = self.config.scatter_count;
+            let heading = b.vel.ang();
+            let speed = b.vel.mag();
+            for i in 0..count {
+                let offset = if count > 1 {
+                    -self.config.scatter_spread + 2.0 * self.config.scatter_spread * i as f32 / (count - 1) as f32
+                } else {
+                    0.0
+                };
+                let mut piece = Bullet::new(b.pos, Pair::polar(heading + offset) * speed);
+                piece.layers = b.layers;
+                piece.owner = b.owner;
+                piece.immune_steps = self.config.bullet_spawn_immunity;
+                pieces.push(piece);
+            }
+        }
+        for piece in pieces {
+            self.add_bullet(piece);
+        }
+    }
+
+    // Kill the oldest bullets until no more than `cap` are live. Bullets of the same age go in
+    // the order they were fired.
+    fn evict_bullets(&self, cap: usize) {
+        let bullets = self.bullets.read().unwrap();
+        let mut live: Vec<(usize, usize, &Identity<Arc<RwLock<Bullet>>>)> = bullets
+            .iter()
+            .map(|b| (b, b.read().unwrap()))
+            .filter(|(_, bullet)| !bullet.dead)
+            .map(|(b, bullet)| (bullet.age, bullet.id, b))
+            .collect();
+        if live.len() <= cap {
+            return;
+        }
+        live.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        let excess = live.len() - cap;
+        for &(_, _, b) in live.iter().take(excess) {
+            b.write().unwrap().dead = true;
+        }
+    }
+
+    // Remove tanks that have been dead for at least `despawn_grace` steps. A tank is kept while
+    // anything outside the world still holds a reference to it (e.g. scoring in progress).
+    pub fn despawn_dead_tanks(&self) {
+        let grace = match self.config.despawn_grace {
+            Some(grace) => grace,
+            None => return,
+        };
+        let steps = self.steps;
+        self.tanks.write().unwrap().retain(|t| {
+            let mut tank = t.write().unwrap();
+            if tank.state != TankState::Dead {
+                return true;
+            }
+            let since = *tank.dead_since.get_or_insert(steps);
+            steps - since < grace || Arc::strong_count(t) > 1
+        });
+    }
+
+    pub fn status(&self) -> MatchStatus {
+        let mut living = BTreeMap::new();
+        for t in self.tanks.read().unwrap().iter() {
+            let t = t.read().unwrap();
+            let count = living.entry(t.team).or_insert(0);
+            if t.state != TankState::Dead {
+                *count += 1;
+            }
+        }
+        MatchStatus {
+            step: self.steps,
+            living,
+            tanks: self.tanks.read().unwrap().len(),
+            bullets: self.bullets.read().unwrap().iter().filter(|b| !b.read().unwrap().dead).count(),
+            finished: self.finished(),
+        }
+    }
+
+    // The zones `pos` is inside of, in configuration order.
+    pub fn zones_at(&self, pos: Pair) -> impl Iterator<Item = &Zone> {
+        self.config.zones.iter().filter(move |z| z.contains(pos))
     }
 
     pub fn finished(&self) -> bool {
@@ -399,29 +1952,247 @@ impl World {
             .read()
             .unwrap()
             .iter()
-            .all(|t| t.read().unwrap().state == TankState::Dead)
+            .all(|t| match t.read().unwrap().state {
+                TankState::Dead | TankState::Halted => true,
+                _ => false,
+            })
     }
 
+    // The queries from here to nearest_on_team are made by tanks partway through their own step,
+    // while the asking tank is locked. They try each tank's lock and pass over any they can't
+    // take, which leaves out the asker and nothing else.
+
+    // Tanks within the arc `bounds` around `pos` and within `scan_range`, on `tm` and not. The
+    // enemies found are marked as scanned.
     pub fn scan(&self, pos: Pair, tm: Team, bounds: (f32, f32)) -> (u32, u32) {
+        let (mut us, mut them) = (0u32, 0u32);
+        for t in self.tanks.read().unwrap().iter() {
+            let mut t = match t.try_write() {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            let a = (t.pos + (-pos)).ang();
+            if !in_arc(a, bounds) || !self.in_scan_range(pos, t.pos) {
+                continue;
+            }
+            if t.team == tm {
+                us += 1;
+            } else {
+                them += 1;
+                t.scans = t.scans.saturating_add(1);
+            }
+        }
+        (us, them)
+    }
+
+    // Bearing from `pos` to the hottest live tank not on `tm` within the arc `bounds` and
+    // `scan_range` (as for `scan`). Ties go to the lowest id.
+    pub fn scan_hottest(&self, pos: Pair, tm: Team, bounds: (f32, f32)) -> Option<f32> {
         self.tanks
             .read()
             .unwrap()
             .iter()
-            .map(|tank| (tank, (tank.read().unwrap().pos + (-pos)).ang()))
-            .filter(|(_t, a)| *a >= bounds.0 && *a < bounds.1)
-            .fold((0u32, 0u32), |(us, them), (t, _a)| {
-                if t.read().unwrap().team == tm {
-                    (us + 1, them)
-                } else {
-                    (us, them + 1)
+            .filter_map(|t| t.try_read().ok().map(|t| (t.id, t.team, t.temp, t.state == TankState::Dead, t.pos)))
+            .filter(|&(_, team, _, dead, p)| team != tm && !dead && self.in_scan_range(pos, p))
+            .map(|(id, _, temp, _, p)| (id, temp, (p + (-pos)).ang()))
+            .filter(|&(_, _, a)| in_arc(a, bounds))
+            .fold(None, |best: Option<(usize, i32, f32)>, cur| match best {
+                Some(b) if b.1 > cur.1 || (b.1 == cur.1 && b.0 < cur.0) => Some(b),
+                _ => Some(cur),
+            })
+            .map(|(_, _, a)| a)
+    }
+
+    fn in_scan_range(&self, from: Pair, to: Pair) -> bool {
+        self.config.scan_range.map_or(true, |range| {
+            let d = to + (-from);
+            d.dot(&d) <= range * range
+        })
+    }
+
+    // The average position of `team`'s living tanks, if it has any.
+    pub fn team_centroid(&self, team: Team) -> Option<Pair> {
+        self.centroid_with(team, None)
+    }
+
+    // As team_centroid, for use while a tank steps: that tank is locked, so it can't be found in
+    // the list, and passes its own position as `stepping` instead.
+    fn centroid_with(&self, team: Team, stepping: Option<Pair>) -> Option<Pair> {
+        let mut sum = stepping.unwrap_or(Pair::zero());
+        let mut count = if stepping.is_some() { 1 } else { 0 };
+        for t in self.tanks.read().unwrap().iter() {
+            if let Ok(t) = t.try_read() {
+                if t.team == team && t.state != TankState::Dead {
+                    sum = sum + t.pos;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 {
+            None
+        } else {
+            Some(sum * (1.0 / count as f32))
+        }
+    }
+
+    // Bearing from `pos` to the nearest live tank not on `tm` that lies within `tolerance`
+    // radians either side of `aim`.
+    pub fn assist_bearing(&self, pos: Pair, tm: Team, aim: f32, tolerance: f32) -> Option<f32> {
+        let mut best: Option<(f32, f32)> = None;
+        for t in self.tanks.read().unwrap().iter() {
+            let t = match t.try_read() {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            if t.team == tm || t.state == TankState::Dead {
+                continue;
+            }
+            let offset = t.pos + (-pos);
+            let bearing = offset.ang();
+            let off_aim = (bearing - aim + ::std::f32::consts::PI).rem_euclid(2.0 * ::std::f32::consts::PI)
+                - ::std::f32::consts::PI;
+            if off_aim.abs() > tolerance {
+                continue;
+            }
+            let dist = offset.mag();
+            if best.map_or(true, |(d, _)| dist < d) {
+                best = Some((dist, bearing));
+            }
+        }
+        best.map(|(_, bearing)| bearing)
+    }
+
+    // Distance and bearing from `pos` to the nearest living tank on `team`.
+    pub fn nearest_on_team(&self, pos: Pair, team: Team) -> Option<(f32, f32)> {
+        let mut best: Option<(f32, f32)> = None;
+        for t in self.tanks.read().unwrap().iter() {
+            let t = match t.try_read() {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            if t.team != team || t.state == TankState::Dead {
+                continue;
+            }
+            let offset = t.pos + (-pos);
+            let dist = offset.mag();
+            if best.map_or(true, |(d, _)| dist < d) {
+                best = Some((dist, offset.ang()));
+            }
+        }
+        best
+    }
+
+    // The team and temperature of the hottest live tank; ties go to the lowest id.
+    pub fn hottest_tank(&self) -> Option<(Team, i32)> {
+        self.extreme_temp(|a, b| a > b)
+    }
+
+    // The team and temperature of the coldest live tank; ties go to the lowest id.
+    pub fn coldest_tank(&self) -> Option<(Team, i32)> {
+        self.extreme_temp(|a, b| a < b)
+    }
+
+    // The live tank whose temperature beats every other's by `better`.
+    fn extreme_temp<F: Fn(i32, i32) -> bool>(&self, better: F) -> Option<(Team, i32)> {
+        let mut best: Option<(usize, Team, i32)> = None;
+        for t in self.tanks.read().unwrap().iter() {
+            let t = t.read().unwrap();
+            if t.state == TankState::Dead {
+                continue;
+            }
+            best = match best {
+                Some(b) if !better(t.temp, b.2) && !(t.temp == b.2 && t.id < b.0) => Some(b),
+                _ => Some((t.id, t.team, t.temp)),
+            };
+        }
+        best.map(|(_, team, temp)| (team, temp))
+    }
+
+    // Count the bullets within the arc `bounds` (bearings from `pos`, as for `scan`) that are on
+    // course to hit a tank at `pos`: closing, with a line of flight passing within hit range.
+    pub fn scan_bullets(&self, pos: Pair, bounds: (f32, f32)) -> u32 {
+        self.bullets
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|b| {
+                let b = b.read().unwrap();
+                let bearing = (b.pos + (-pos)).ang();
+                if b.dead || !in_arc(bearing, bounds) {
+                    return false;
                 }
+                let to_us = pos + (-b.pos);
+                let speed = b.vel.mag();
+                let along = b.vel.dot(&to_us);
+                if speed == 0.0 || along <= 0.0 {
+                    return false;
+                }
+                // Distance from `pos` to the line the bullet is travelling along
+                let along = along / speed;
+                let miss = (to_us.dot(&to_us) - along * along).max(0.0).sqrt();
+                miss < self.config.tank_radius + self.config.bullet_radius_of(b.kind)
             })
+            .count() as u32
+    }
+
+    fn record_upcall(&self, tank: usize, upcall: &Upcall) {
+        if self.config.record_upcalls {
+            self.upcall_log.borrow_mut().push(UpcallRecord {
+                step: self.steps,
+                tank,
+                upcall: upcall.clone(),
+                written: None,
+            });
+        }
     }
 
-    fn do_explode(&self, pos: Pair, rad: f32) {
+    // Note what answering tank `tank`'s latest recorded upcall wrote into its memory.
+    fn record_written(&self, tank: usize, bytes: &[u8]) {
+        if self.config.record_upcalls {
+            if let Some(record) = self.upcall_log.borrow_mut().last_mut() {
+                debug_assert_eq!(record.tank, tank);
+                record.written = Some(bytes.to_vec());
+            }
+        }
+    }
+
+    // Every upcall serviced so far, when `record_upcalls` is on.
+    pub fn upcall_log(&self) -> Ref<Vec<UpcallRecord>> {
+        self.upcall_log.borrow()
+    }
+
+    // Events from the most recent step.
+    pub fn events(&self) -> Ref<Vec<GameEvent>> {
+        self.events.borrow()
+    }
+
+    pub fn add_effect(&self, effect: Effect) {
+        self.effects.borrow_mut().push(effect);
+    }
+
+    // Effects produced by the most recent step.
+    pub fn effects(&self) -> Ref<Vec<Effect>> {
+        self.effects.borrow()
+    }
+
+    fn do_explode(&self, pos: Pair, rad: f32, source: Option<usize>) {
+        self.effects.borrow_mut().push(Effect::Explosion { pos, rad });
         for t in self.tanks.write().unwrap().iter_mut() {
-            if (t.read().unwrap().pos + (-pos)).limag() <= rad {
-                t.write().unwrap().state = TankState::Dead;
+            let mut tank = t.write().unwrap();
+            let dist = (tank.pos + (-pos)).mag();
+            if dist > rad || tank.protection > 0 || Some(tank.id) == source {
+                continue;
+            }
+            match self.config.explode_damage {
+                // Full damage at the center, falling off linearly to none at the edge
+                Some(damage) => {
+                    let falloff = if rad > 0.0 { 1.0 - dist / rad } else { 1.0 };
+                    tank.health = tank.health.saturating_sub((damage as f32 * falloff).round() as i32);
+                    if tank.health <= 0 {
+                        tank.kill(DeathCause::Explosion);
+                    }
+                }
+                None => tank.kill(DeathCause::Explosion),
             }
         }
     }
@@ -429,6 +2200,1973 @@ impl World {
     pub fn explode(&self, pos: Pair, rad: f32) {
         self.action_queue
             .borrow_mut()
-            .push(WorldAction::Explode(pos, rad));
+            .push(WorldAction::Explode(pos, rad, None));
+    }
+
+    // An explosion set off by tank `source`, which is spared the blast (it's already dead).
+    pub fn explode_from(&self, source: usize, pos: Pair, rad: f32) {
+        self.action_queue
+            .borrow_mut()
+            .push(WorldAction::Explode(pos, rad, Some(source)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vm::testprog::*;
+
+    fn world_of_dummies() -> World {
+        let mut world = Configuration::default().build();
+        for (i, team) in [0, 1, 0, 1].iter().enumerate() {
+            let pos = Pair { x: 100.0 + 50.0 * i as f32, y: 100.0 };
+            world.add_tank(Tank::dummy(pos, *team)).unwrap();
+        }
+        world
+    }
+
+    #[test]
+    fn dummies_step_the_same_in_any_order() {
+        let mut world = world_of_dummies();
+        let owner = world.tanks.read().unwrap()[0].read().unwrap().id;
+        let mut bullet = Bullet::new(Pair { x: 120.0, y: 100.0 }, Pair { x: 5.0, y: 0.0 });
+        bullet.owner = Some(owner);
+        world.add_bullet(bullet);
+        world.explode(Pair { x: 250.0, y: 100.0 }, 10.0);
+        for _ in 0..5 {
+            world.assert_deterministic_step();
+        }
+    }
+
+    // A target just clockwise of east, which only a scan swept back across 0 can see
+    fn swept_scan_count(swept: bool) -> u64 {
+        let mut config = Configuration::default();
+        config.swept_scan = swept;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::polar(-0.2) * 100.0, 1)).unwrap();
+        let mut scanner = Tank::dummy(Pair::zero(), 0);
+        scanner.prev_angle = 0.0;
+        scanner.angle = 0.5;
+        scanner.scan(&world, 0.2, 0.4)
+    }
+
+    #[test]
+    fn swept_scan_wraps_through_zero() {
+        assert_eq!(swept_scan_count(false), 0);
+        assert_eq!(swept_scan_count(true), 1);
+    }
+
+    #[test]
+    fn shot_heat_follows_the_kind_fired() {
+        let mut config = Configuration::default();
+        config.shoot_heat = 10;
+        config.heat_per_bullet_speed = 2.0;
+        config.bullet_v = 5.0;
+        config.heavy_speed = 0.5;
+        assert_eq!(config.shot_heat(BulletKind::Standard), 20);
+        assert_eq!(config.shot_heat(BulletKind::Heavy), 15);
+        // Clamped speed, clamped heat
+        config.max_bullet_v = 4.0;
+        assert_eq!(config.bullet_v_of(BulletKind::Standard), 4.0);
+        assert_eq!(config.shot_heat(BulletKind::Standard), 18);
+    }
+
+    #[test]
+    fn gravity_cannot_push_bullets_past_max_speed() {
+        let mut config = Configuration::default();
+        config.gravity = Pair { x: 0.0, y: -3.0 };
+        config.max_bullet_v = 10.0;
+        let world = config.build();
+        let mut bullet = Bullet::new(Pair::zero(), Pair { x: 5.0, y: 0.0 });
+        for _ in 0..20 {
+            bullet.step(&world);
+            assert!(bullet.vel.mag() <= 10.0 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn invalid_configurations_are_not_built() {
+        let mut config = Configuration::default();
+        config.max_bullet_v = config.bullet_v / 2.0;
+        match config.try_build() {
+            Err(ConfigError::BulletTooFast { .. }) => (),
+            other => panic!("built anyway: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn team_meta_round_trips() {
+        let meta = TeamMeta {
+            name: "red".into(),
+            color: "#ff0000".into(),
+        };
+        let json = serde_json::to_string(&meta).unwrap();
+        assert_eq!(serde_json::from_str::<TeamMeta>(&json).unwrap(), meta);
+    }
+
+    #[test]
+    #[should_panic(expected = "depends on entity order")]
+    fn order_dependent_system_is_caught() {
+        let mut world = world_of_dummies();
+        // Nudge whichever tank happens to be stored first
+        world.assert_deterministic_with(|w| {
+            w.tanks.read().unwrap()[0].write().unwrap().pos.x += 1.0;
+            w.step();
+        });
+    }
+
+    #[test]
+    fn shots_leave_the_muzzle_within_the_spread() {
+        let mut config = Configuration::default();
+        config.bullet_spread = 0.1;
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        tank.aim = 1.0;
+        for _ in 0..50 {
+            assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        }
+        let muzzle = Pair::polar(1.0) * world.config.bullet_s;
+        let mut headings = Vec::new();
+        world.with_bullets(|bullets| {
+            for b in bullets {
+                let b = b.read().unwrap();
+                assert!(b.pos.approx_eq(&muzzle, 1e-3));
+                assert!((b.vel.mag() - world.config.bullet_v).abs() < 1e-4);
+                assert!((b.vel.ang() - 1.0).abs() <= 0.1 + 1e-4);
+                headings.push(b.vel.ang());
+            }
+        });
+        // The spread is actually used, not just allowed
+        assert!(headings.iter().any(|&h| (h - 1.0).abs() > 0.01));
+    }
+
+    #[test]
+    fn without_spread_shots_follow_aim_exactly() {
+        let world = Configuration::default().build();
+        let mut tank = Tank::dummy(Pair { x: 10.0, y: -20.0 }, 0);
+        tank.aim = 2.5;
+        tank.fire(&world, BulletKind::Standard);
+        world.with_bullets(|bullets| {
+            let b = bullets[0].read().unwrap();
+            assert!(b.pos.approx_eq(&(Pair { x: 10.0, y: -20.0 } + Pair::polar(2.5) * 30.0), 1e-3));
+            assert!((b.vel.ang() - 2.5).abs() < 1e-5);
+        });
+    }
+
+    #[test]
+    fn teammates_draw_from_their_own_streams() {
+        let mut config = Configuration::default();
+        config.bullet_spread = 0.5;
+        let mut world = config.build();
+        for x in &[0.0, 100.0] {
+            world.add_tank(Tank::dummy(Pair { x: *x, y: 0.0 }, 0)).unwrap();
+        }
+        let tanks: Vec<_> = world.tanks.read().unwrap().iter().cloned().collect();
+        for t in &tanks {
+            t.write().unwrap().fire(&world, BulletKind::Standard);
+        }
+        let headings: Vec<f32> = world.with_bullets(|bullets| {
+            bullets.iter().map(|b| b.read().unwrap().vel.ang()).collect()
+        });
+        assert_ne!(headings[0], headings[1]);
+    }
+
+    fn collisions_with_radii(tank_radius: f32, bullet_radius: f32) -> (usize, usize) {
+        let mut config = Configuration::default();
+        config.tank_radius = tank_radius;
+        config.bullet_radius = bullet_radius;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 9.0, y: 0.0 }, 1)).unwrap();
+        let mut bullet = Bullet::new(Pair { x: 0.0, y: -5.5 }, Pair::zero());
+        bullet.immune_steps = 0;
+        world.add_bullet(bullet);
+        let pairs = world.detect_collisions();
+        let tank_tank = pairs
+            .iter()
+            .filter(|(_, b)| match b {
+                EntityRef::Tank(_) => true,
+                _ => false,
+            })
+            .count();
+        (tank_tank, pairs.len() - tank_tank)
+    }
+
+    #[test]
+    fn tanks_and_bullets_collide_at_their_own_radii() {
+        assert_eq!(collisions_with_radii(5.0, 1.0), (1, 1));
+        // Smaller tanks miss each other, and the bullet only just reaches
+        assert_eq!(collisions_with_radii(4.0, 2.0), (0, 1));
+        assert_eq!(collisions_with_radii(5.0, 0.4), (1, 0));
+    }
+
+    fn program_tank(world: &World, module: Module) -> Tank {
+        TankBuilder::new(Pair::zero(), 0, module.build(), world.config.clone())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn tanks_whose_program_returns_halt() {
+        let mut world = Configuration::default().build();
+        let tank = program_tank(&world, Module::new(vec![NOP, NOP, NOP]));
+        world.add_tank(tank).unwrap();
+        assert!(!world.finished());
+        world.step();
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().state, TankState::Halted);
+        assert!(world.finished());
+    }
+
+    #[test]
+    fn alter_cooldown_counts_whole_steps() {
+        let mut config = Configuration::default();
+        config.alter_cooldown = 3;
+        let mut world = config.build();
+        let tank = program_tank(&world, Module::new(forever(&call(0))).with_imports(&["forward"]));
+        world.add_tank(tank).unwrap();
+        for _ in 0..7 {
+            world.step();
+        }
+        // Moved on steps 0, 3 and 6 only
+        let x = world.tanks.read().unwrap()[0].read().unwrap().pos.x;
+        assert_eq!(x, 3.0 * world.config.tank_v);
+    }
+
+    fn ids(world: &World) -> Vec<usize> {
+        world.with_tanks(|tanks| tanks.iter().map(|t| t.read().unwrap().id).collect())
+    }
+
+    #[test]
+    fn dead_tanks_despawn_without_disturbing_ids() {
+        let mut config = Configuration::default();
+        config.despawn_grace = Some(2);
+        let mut world = config.build();
+        for x in &[0.0, 100.0, 200.0] {
+            world.add_tank(Tank::dummy(Pair { x: *x, y: 0.0 }, 0)).unwrap();
+        }
+        world.tanks.read().unwrap()[1].write().unwrap().kill(DeathCause::Collision);
+        world.step();
+        world.step();
+        assert_eq!(ids(&world), vec![0, 1, 2]);
+        world.step();
+        assert_eq!(ids(&world), vec![0, 2]);
+        // Ids are never handed out twice
+        let id = world.add_tank(Tank::dummy(Pair::zero(), 1)).unwrap();
+        assert_eq!(id, 3);
+    }
+
+    #[test]
+    fn referenced_dead_tanks_are_kept() {
+        let mut config = Configuration::default();
+        config.despawn_grace = Some(0);
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let held = world.tanks.read().unwrap()[0].clone();
+        held.write().unwrap().kill(DeathCause::Collision);
+        world.step();
+        assert_eq!(ids(&world), vec![0]);
+        core::mem::drop(held);
+        world.step();
+        assert!(ids(&world).is_empty());
+    }
+
+    #[test]
+    fn builder_sets_what_it_is_given() {
+        let world = Configuration::default().build();
+        let prog = Module::new(forever(&[NOP])).build();
+        let tank = TankBuilder::new(Pair { x: 1.0, y: 2.0 }, 3, prog, world.config.clone())
+            .with_aim(0.5)
+            .with_angle(1.5)
+            .with_temp(40)
+            .with_health(7)
+            .with_instrs_per_step(99)
+            .with_layers(0b10)
+            .build()
+            .unwrap();
+        assert_eq!(tank.pos, Pair { x: 1.0, y: 2.0 });
+        assert_eq!(tank.prev_pos, tank.pos);
+        assert_eq!((tank.team, tank.aim, tank.angle, tank.prev_angle), (3, 0.5, 1.5, 1.5));
+        assert_eq!((tank.temp, tank.health, tank.instrs_per_step, tank.layers), (40, 7, 99, 0b10));
+        assert_eq!(tank.state, TankState::Free);
+    }
+
+    #[test]
+    fn builder_defaults_come_from_the_config() {
+        let mut config = Configuration::default();
+        config.instrs_per_step = 77;
+        config.tank_health = 55;
+        config.default_layers = 0b100;
+        let world = config.build();
+        let tank = program_tank(&world, Module::new(forever(&[NOP])));
+        assert_eq!((tank.instrs_per_step, tank.health, tank.layers), (77, 55, 0b100));
+        // A bad module is reported, not panicked over
+        let bad = TankBuilder::new(Pair::zero(), 0, vec![1, 2, 3], world.config.clone()).build();
+        assert!(bad.is_err());
+    }
+
+    fn point_blank_survives(immunity: usize) -> bool {
+        let mut config = Configuration::default();
+        config.bullet_s = 0.0;
+        config.bullet_spawn_immunity = immunity;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let tank = world.tanks.read().unwrap()[0].clone();
+        tank.write().unwrap().fire(&world, BulletKind::Standard);
+        for _ in 0..5 {
+            world.step();
+        }
+        let alive = tank.read().unwrap().state != TankState::Dead;
+        alive
+    }
+
+    #[test]
+    fn fresh_bullets_spare_the_firer() {
+        assert!(point_blank_survives(3));
+        assert!(!point_blank_survives(0));
+    }
+
+    #[test]
+    fn bullets_never_spawn_outside_the_world() {
+        let world = Configuration::default().build();
+        let mut tank = Tank::dummy(Pair { x: 495.0, y: 0.0 }, 0);
+        tank.fire(&world, BulletKind::Standard);
+        let pos = world.with_bullets(|bullets| bullets[0].read().unwrap().pos);
+        assert_eq!(pos, Pair { x: 500.0, y: 0.0 });
+    }
+
+    #[test]
+    fn fast_bullets_cannot_tunnel_through_tanks() {
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        // Starts and ends the step well clear of the tank, but passes right through it
+        let mut bullet = Bullet::new(Pair { x: -12.0, y: 0.5 }, Pair { x: 20.0, y: 0.0 });
+        bullet.immune_steps = 0;
+        world.add_bullet(bullet);
+        world.step();
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().state, TankState::Dead);
+    }
+
+    #[test]
+    fn gravity_and_drag_bend_bullets() {
+        let mut config = Configuration::default();
+        config.gravity = Pair { x: 0.0, y: -1.0 };
+        config.drag = 0.5;
+        let world = config.build();
+        let mut bullet = Bullet::new(Pair::zero(), Pair { x: 4.0, y: 0.0 });
+        bullet.step(&world);
+        assert_eq!(bullet.vel, Pair { x: 2.0, y: -0.5 });
+        assert_eq!(bullet.pos, Pair { x: 2.0, y: -0.5 });
+        bullet.step(&world);
+        assert_eq!(bullet.vel, Pair { x: 1.0, y: -0.75 });
+        assert_eq!(bullet.pos, Pair { x: 3.0, y: -1.25 });
+        // By default bullets fly straight and true
+        let world = Configuration::default().build();
+        let mut bullet = Bullet::new(Pair::zero(), Pair { x: 4.0, y: 0.0 });
+        bullet.step(&world);
+        assert_eq!(bullet.vel, Pair { x: 4.0, y: 0.0 });
+    }
+
+    #[test]
+    fn impacts_and_explosions_show_up_as_effects() {
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let mut bullet = Bullet::new(Pair { x: -10.5, y: 0.0 }, Pair { x: 1.0, y: 0.0 });
+        bullet.immune_steps = 0;
+        world.add_bullet(bullet);
+        for _ in 0..5 {
+            world.step();
+        }
+        match &world.effects()[..] {
+            [Effect::Impact { pos }] => assert_eq!(*pos, Pair { x: -5.5, y: 0.0 }),
+            other => panic!("{:?}", other),
+        }
+        world.explode(Pair { x: 300.0, y: 0.0 }, 10.0);
+        world.step();
+        match &world.effects()[..] {
+            [Effect::Explosion { pos, rad }] => assert_eq!((*pos, *rad), (Pair { x: 300.0, y: 0.0 }, 10.0)),
+            other => panic!("{:?}", other),
+        }
+        // Effects only last the step they happened in
+        world.step();
+        assert!(world.effects().is_empty());
+    }
+
+    #[test]
+    fn adaptive_budget_is_shared_by_running_tanks() {
+        let mut config = Configuration::default();
+        config.adaptive_budget = Some(100);
+        let mut world = config.build();
+        for i in 0..2 {
+            let mut tank = program_tank(&world, Module::new(forever(&[NOP])));
+            tank.pos = Pair { x: 100.0 * i as f32, y: 0.0 };
+            tank.prev_pos = tank.pos;
+            world.add_tank(tank).unwrap();
+        }
+        // Dummies don't run, so they get no share
+        world.add_tank(Tank::dummy(Pair { x: 200.0, y: 0.0 }, 1)).unwrap();
+        world.step();
+        assert_eq!(world.tank_budget(), Some(50));
+        world.tanks.read().unwrap()[0].write().unwrap().kill(DeathCause::Collision);
+        world.step();
+        assert_eq!(world.tank_budget(), Some(100));
+        // Without it, each tank runs its own instrs_per_step
+        let world = Configuration::default().build();
+        assert_eq!(world.tank_budget(), None);
+    }
+
+    #[test]
+    fn traces_keep_the_latest_upcalls() {
+        let mut config = Configuration::default();
+        config.upcall_trace_len = 2;
+        config.trace_team = Some(0);
+        let mut world = config.build();
+        for team in 0..2 {
+            let mut tank = program_tank(&world, Module::new(forever(&call(0))).with_imports(&["yield"]));
+            tank.team = team;
+            tank.pos = Pair { x: 100.0 * team as f32, y: 0.0 };
+            tank.prev_pos = tank.pos;
+            world.add_tank(tank).unwrap();
+        }
+        for _ in 0..3 {
+            world.step();
+        }
+        let tanks = world.tanks.read().unwrap();
+        let traced = tanks[0].read().unwrap();
+        let steps: Vec<usize> = traced.recent_upcalls().iter().map(|&(step, _)| step).collect();
+        assert_eq!(steps, vec![1, 2]);
+        assert_eq!(traced.info().upcalls, Some(vec!["1: none".to_string(), "2: none".to_string()]));
+        // Every tank keeps a trace, but only the chosen team's is published
+        let other = tanks[1].read().unwrap();
+        assert_eq!(other.recent_upcalls().len(), 2);
+        assert_eq!(other.info().upcalls, None);
+    }
+
+    #[test]
+    fn scan_bullets_counts_only_incoming_fire() {
+        let world = Configuration::default().build();
+        let incoming = [
+            (Pair { x: 50.0, y: 0.0 }, Pair { x: -5.0, y: 0.0 }),
+            (Pair { x: 0.0, y: 50.0 }, Pair { x: 0.0, y: -5.0 }),
+        ];
+        let harmless = [
+            (Pair { x: 50.0, y: 0.0 }, Pair { x: 5.0, y: 0.0 }), // Going away
+            (Pair { x: 50.0, y: 1.0 }, Pair { x: 0.0, y: 5.0 }), // Crossing, far ahead
+            (Pair { x: 50.0, y: -20.0 }, Pair { x: -5.0, y: 0.0 }), // Will pass wide
+        ];
+        for &(pos, vel) in incoming.iter().chain(harmless.iter()) {
+            world.add_bullet(Bullet::new(pos, vel));
+        }
+        let east = (-0.1, 0.1);
+        assert_eq!(world.scan_bullets(Pair::zero(), east), 1);
+        assert_eq!(world.scan_bullets(Pair::zero(), (0.0, 2.0 * ::std::f32::consts::PI)), 2);
+        assert_eq!(world.scan_bullets(Pair { x: 500.0, y: 0.0 }, east), 0);
+    }
+
+    // Have a tank without a program service `upcall`, as if its program had just made it.
+    fn service(tank: &mut Tank, world: &World, upcall: Upcall) {
+        tank.state = TankState::Pending(upcall);
+        tank.step(world);
+    }
+
+    fn gps_x(tank: &mut Tank, world: &World) -> f32 {
+        let slot = Arc::new(Mutex::new(None));
+        service(tank, world, Upcall::GPSX(slot.clone()));
+        let x = slot.lock().unwrap().unwrap();
+        x
+    }
+
+    #[test]
+    fn sensor_noise_is_bounded_and_seeded() {
+        let world = Configuration::default().build();
+        let mut tank = Tank::dummy(Pair { x: 10.0, y: 0.0 }, 0);
+        assert_eq!(gps_x(&mut tank, &world), 10.0);
+
+        let mut config = Configuration::default();
+        config.sensor_noise = 0.5;
+        let world = config.build();
+        let readings: Vec<f32> = (0..20).map(|_| gps_x(&mut tank, &world)).collect();
+        assert!(readings.iter().all(|x| (x - 10.0).abs() <= 1.5));
+        assert!(readings.iter().any(|&x| x != 10.0));
+        // The same seed hears the same noise
+        let mut twin = Tank::dummy(Pair { x: 10.0, y: 0.0 }, 0);
+        let mut tank = Tank::dummy(Pair { x: 10.0, y: 0.0 }, 0);
+        assert_eq!(gps_x(&mut tank, &world), gps_x(&mut twin, &world));
+    }
+
+    #[test]
+    fn throttle_keeps_tanks_moving_between_upcalls() {
+        let world = Configuration::default().build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        service(&mut tank, &world, Upcall::SetThrottle(0.5));
+        tank.step(&world);
+        tank.step(&world);
+        assert_eq!(tank.pos, Pair { x: 1.5, y: 0.0 });
+        assert_eq!(tank.vel, Pair { x: 0.5, y: 0.0 });
+        service(&mut tank, &world, Upcall::SetThrottle(7.0));
+        assert_eq!(tank.throttle, 1.0);
+        service(&mut tank, &world, Upcall::SetThrottle(::std::f32::NAN));
+        assert_eq!(tank.throttle, 0.0);
+        // Dead tanks stop
+        service(&mut tank, &world, Upcall::SetThrottle(-1.0));
+        tank.kill(DeathCause::Collision);
+        let pos = tank.pos;
+        tank.step(&world);
+        assert_eq!(tank.pos, pos);
+    }
+
+    fn collide_on_layers(a: u32, b: u32, bullet: u32) -> usize {
+        let mut world = Configuration::default().build();
+        for (x, layers) in &[(0.0, a), (8.0, b)] {
+            let mut tank = Tank::dummy(Pair { x: *x, y: 0.0 }, 0);
+            tank.layers = *layers;
+            world.add_tank(tank).unwrap();
+        }
+        let mut shot = Bullet::new(Pair { x: 4.0, y: 3.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        shot.layers = bullet;
+        world.add_bullet(shot);
+        world.detect_collisions().len()
+    }
+
+    #[test]
+    fn only_entities_sharing_a_layer_collide() {
+        // Both tanks, and the bullet with each
+        assert_eq!(collide_on_layers(0b1, 0b1, 0b1), 3);
+        assert_eq!(collide_on_layers(0b01, 0b10, 0b00), 0);
+        assert_eq!(collide_on_layers(0b01, 0b11, 0b10), 2);
+        assert_eq!(collide_on_layers(0b01, 0b11, 0b01), 3);
+    }
+
+    #[test]
+    fn tanks_can_be_found_by_team() {
+        let world = world_of_dummies();
+        let ids = |tanks: Vec<Identity<Arc<RwLock<Tank>>>>| -> Vec<usize> {
+            tanks.iter().map(|t| t.read().unwrap().id).collect()
+        };
+        let id = |tank: Option<Identity<Arc<RwLock<Tank>>>>| -> Option<usize> {
+            let id = tank.as_ref().map(|t| t.read().unwrap().id);
+            id
+        };
+        assert_eq!(ids(world.tanks_by_team(1)), vec![1, 3]);
+        assert!(world.tanks_by_team(7).is_empty());
+        assert_eq!(id(world.tank_by_team(1)), Some(1));
+        // The first live one
+        world.tanks.read().unwrap()[1].write().unwrap().kill(DeathCause::Collision);
+        assert_eq!(id(world.tank_by_team(1)), Some(3));
+        assert_eq!(ids(world.tanks_by_team(1)), vec![1, 3]);
+        world.tanks.read().unwrap()[3].write().unwrap().kill(DeathCause::Collision);
+        assert!(world.tank_by_team(1).is_none());
+    }
+
+    fn collision_ids(world: &World) -> Vec<(char, usize, char, usize)> {
+        let id = |e: &EntityRef| match *e {
+            EntityRef::Tank(ref t) => ('t', t.read().unwrap().id),
+            EntityRef::Bullet(ref b) => ('b', b.read().unwrap().id),
+        };
+        let mut ids: Vec<_> = world
+            .detect_collisions()
+            .iter()
+            .map(|&(ref a, ref b)| {
+                let (ak, ai) = id(a);
+                let (bk, bi) = id(b);
+                (ak, ai, bk, bi)
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn scattered_world(quadtree_threshold: usize) -> World {
+        let mut world = Configuration {
+            quadtree_threshold,
+            bullet_collisions: true,
+            ..Configuration::default()
+        }
+        .build();
+        for x in &[-200.0, 0.0, 8.0, 150.0, 300.0] {
+            world.add_tank(Tank::dummy(Pair { x: *x, y: 40.0 }, 0)).unwrap();
+        }
+        let shots = [
+            (Pair { x: 4.0, y: 43.0 }, Pair::zero()),
+            (Pair { x: -200.0, y: 36.0 }, Pair { x: 0.0, y: 20.0 }),
+            (Pair { x: 400.0, y: -400.0 }, Pair { x: 1.0, y: 0.0 }),
+            (Pair { x: 400.5, y: -400.0 }, Pair::zero()),
+            (Pair { x: 150.0, y: 100.0 }, Pair::zero()),
+        ];
+        for &(pos, vel) in shots.iter() {
+            let mut shot = Bullet::new(pos + (-vel), vel);
+            shot.pos = pos;
+            shot.immune_steps = 0;
+            world.add_bullet(shot);
+        }
+        world
+    }
+
+    #[test]
+    fn quadtree_and_pairwise_checks_agree() {
+        let pairwise = collision_ids(&scattered_world(usize::max_value()));
+        let tree = collision_ids(&scattered_world(0));
+        assert_eq!(pairwise, tree);
+        assert_eq!(
+            pairwise,
+            vec![
+                ('b', 2, 'b', 3),
+                ('t', 0, 'b', 1),
+                ('t', 1, 'b', 0),
+                ('t', 1, 't', 2),
+                ('t', 2, 'b', 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscribers_hear_shots_deaths_and_the_end() {
+        let mut world = Configuration::default().build();
+        let body = [call(0), vec![DROP]].concat();
+        let tank = program_tank(&world, Module::new(body).with_imports(&["fire"]));
+        world.add_tank(tank).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 0.0, y: 200.0 }, 1)).unwrap();
+        let mut shot = Bullet::new(Pair { x: 0.0, y: 203.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        let rx = world.subscribe();
+
+        // The tank fires and halts within the step, as the dummy is hit
+        world.step();
+        assert!(world.finished());
+        let events: Vec<GameEvent> = rx.try_iter().collect();
+        assert_eq!(events.len(), 3, "{:?}", events);
+        match (&events[0], &events[1], &events[2]) {
+            (
+                GameEvent::Shot { tank: 0, team: 0, bullet: 1 },
+                GameEvent::Death { tank: 1, team: 1 },
+                GameEvent::MatchEnd { step: 0 },
+            ) => (),
+            _ => panic!("unexpected events: {:?}", events),
+        }
+        assert_eq!(world.events().len(), 3);
+
+        // A hung-up subscriber doesn't stop the world
+        core::mem::drop(rx);
+        world.add_tank(Tank::dummy(Pair { x: 0.0, y: -200.0 }, 2)).unwrap();
+        let mut shot = Bullet::new(Pair { x: 0.0, y: -203.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        world.step();
+        assert_eq!(world.events().len(), 1);
+        assert!(world.event_tx.is_none());
+    }
+
+    #[test]
+    fn dummies_sit_still_but_can_be_seen_and_hit() {
+        let mut world = world_of_dummies();
+        // They start out halted, so a world of nothing else is already over
+        assert!(world.finished());
+        assert_eq!(world.scan(Pair { x: 0.0, y: 100.0 }, 0, (-0.1, 0.1)), (2, 2));
+
+        let mut shot = Bullet::new(Pair { x: 150.0, y: 103.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        world.step();
+        let tanks = world.tanks.read().unwrap();
+        for (i, t) in tanks.iter().enumerate() {
+            let t = t.read().unwrap();
+            assert_eq!(t.pos, Pair { x: 100.0 + 50.0 * i as f32, y: 100.0 });
+            let expected = if i == 1 { TankState::Dead } else { TankState::Halted };
+            assert_eq!(t.state, expected);
+        }
+    }
+
+    #[test]
+    fn tanks_cannot_spawn_outside_the_world() {
+        let mut world = Configuration::default().build();
+        let outside = Pair { x: 500.5, y: 0.0 };
+        assert_eq!(
+            world.add_tank(Tank::dummy(outside, 0)),
+            Err(SpawnError::OutOfBounds(outside))
+        );
+        assert!(world.tanks.read().unwrap().is_empty());
+        // The edge itself is in bounds, and the rejected tank didn't use up an id
+        assert_eq!(world.add_tank(Tank::dummy(Pair { x: 500.0, y: -500.0 }, 0)), Ok(0));
+    }
+
+    #[test]
+    fn tank_states_round_trip_through_serde() {
+        for state in &[TankState::Dead, TankState::Halted, TankState::Free] {
+            let json = serde_json::to_string(state).unwrap();
+            assert_eq!(&serde_json::from_str::<TankState>(&json).unwrap(), state);
+        }
+
+        // Return slots carry whatever value they hold so far
+        let pending = TankState::Pending(Upcall::Scan(1.0, 2.0, Arc::new(Mutex::new(Some(5)))));
+        let json = serde_json::to_string(&pending).unwrap();
+        match serde_json::from_str::<TankState>(&json).unwrap() {
+            TankState::Pending(Upcall::Scan(hl, hu, rv)) => {
+                assert_eq!((hl, hu), (1.0, 2.0));
+                assert_eq!(*rv.lock().unwrap(), Some(5));
+            }
+            other => panic!("expected a pending scan, got {:?}", other),
+        }
+        let json = serde_json::to_string(&Upcall::GPSX(Arc::new(Mutex::new(None)))).unwrap();
+        match serde_json::from_str::<Upcall>(&json).unwrap() {
+            Upcall::GPSX(rv) => assert_eq!(*rv.lock().unwrap(), None),
+            other => panic!("expected GPSX, got {:?}", other),
+        }
+    }
+
+    fn fire_upcall(tank: &mut Tank, world: &World) -> i32 {
+        let slot = Arc::new(Mutex::new(None));
+        service(tank, world, Upcall::Fire(slot.clone()));
+        let status = slot.lock().unwrap().unwrap();
+        status
+    }
+
+    #[test]
+    fn fire_says_why_a_shot_did_not_go_out() {
+        let mut config = Configuration::default();
+        config.alter_cooldown = 2;
+        config.fire_heat_limit = Some(30);
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        assert_eq!(fire_upcall(&mut tank, &world), FireStatus::Fired as i32);
+        assert_eq!(fire_upcall(&mut tank, &world), FireStatus::Cooldown as i32);
+        // Cooled by two idle steps, but still too hot for another shot's worth of heat
+        assert_eq!(tank.temp, 24);
+        assert_eq!(fire_upcall(&mut tank, &world), FireStatus::TooHot as i32);
+        assert_eq!(world.bullets.read().unwrap().len(), 1);
+
+        let mut config = Configuration::default();
+        config.max_bullets_per_tank = Some(2);
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::BulletCap);
+        world.bullets.read().unwrap()[0].write().unwrap().dead = true;
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+    }
+
+    #[test]
+    fn overheated_guns_jam_or_scatter() {
+        let mut config = Configuration::default();
+        config.jam_heat = Some(50);
+        config.jam_chance = 1.0;
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        tank.temp = 51;
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Jammed);
+        assert_eq!(tank.temp, 51);
+        assert!(world.bullets.read().unwrap().is_empty());
+        // At the limit is still fine
+        tank.temp = 50;
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+
+        let mut config = Configuration::default();
+        config.jam_heat = Some(50);
+        config.jam_chance = 0.0;
+        config.jam_spread = 0.5;
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        tank.aim = 1.0;
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        tank.temp = 1000;
+        for _ in 0..50 {
+            assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        }
+        let headings: Vec<f32> = world.with_bullets(|bullets| {
+            bullets.iter().map(|b| b.read().unwrap().vel.ang() - 1.0).collect()
+        });
+        assert!(headings[0].abs() < 1e-5);
+        assert!(headings[1..].iter().all(|h| h.abs() <= 0.5 + 1e-4));
+        assert!(headings[1..].iter().any(|h| h.abs() > 0.01));
+    }
+
+    #[test]
+    fn queued_explosions_go_off_in_order() {
+        let mut world = Configuration::default().build();
+        world.explode(Pair { x: 10.0, y: 0.0 }, 5.0);
+        world.explode(Pair { x: -10.0, y: 0.0 }, 7.0);
+        world.explode(Pair { x: 0.0, y: 10.0 }, 9.0);
+        world.step();
+        let blasts: Vec<(f32, f32, f32)> = world
+            .effects()
+            .iter()
+            .filter_map(|e| match *e {
+                Effect::Explosion { pos, rad } => Some((pos.x, pos.y, rad)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(blasts, vec![(10.0, 0.0, 5.0), (-10.0, 0.0, 7.0), (0.0, 10.0, 9.0)]);
+        // Each goes off once
+        world.step();
+        assert!(world.effects().is_empty());
+    }
+
+    #[test]
+    fn armed_tanks_explode_after_the_delay() {
+        let mut config = Configuration::default();
+        config.explode_arm_delay = 3;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 20.0, y: 0.0 }, 1)).unwrap();
+        {
+            let tanks = world.tanks.read().unwrap();
+            service(&mut tanks[0].write().unwrap(), &world, Upcall::Explode);
+            assert_eq!(tanks[0].read().unwrap().info().arming, Some(3));
+        }
+        let states = |world: &World| -> (TankState, TankState) {
+            let tanks = world.tanks.read().unwrap();
+            let states = (tanks[0].read().unwrap().state.clone(), tanks[1].read().unwrap().state.clone());
+            states
+        };
+        for _ in 0..2 {
+            world.step();
+            assert!(world.effects().is_empty());
+            assert_ne!(states(&world).1, TankState::Dead);
+        }
+        world.step();
+        assert_eq!(states(&world), (TankState::Dead, TankState::Dead));
+        let tanks = world.tanks.read().unwrap();
+        let bomber = tanks[0].read().unwrap();
+        assert_eq!(bomber.death_cause, Some(DeathCause::SelfDestruct));
+        assert_eq!(bomber.arming, None);
+    }
+
+    #[test]
+    fn teams_past_255_stay_distinct() {
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 44)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 200.0, y: 0.0 }, 300)).unwrap();
+        assert_eq!(world.scan(Pair::zero(), 300, (-0.1, 0.1)), (1, 1));
+        assert_eq!(world.tanks_by_team(300).len(), 1);
+        assert_eq!(world.tanks_by_team(44).len(), 1);
+    }
+
+    #[test]
+    fn only_the_stepped_teams_run() {
+        let mut world = Configuration::default().build();
+        for team in 0..2 {
+            let module = Module::new(forever(&call(0))).with_imports(&["forward"]);
+            let mut tank = program_tank(&world, module);
+            tank.team = team;
+            tank.pos = Pair { x: 0.0, y: 100.0 * team as f32 };
+            tank.prev_pos = tank.pos;
+            world.add_tank(tank).unwrap();
+        }
+        world.add_bullet(Bullet::new(Pair { x: 0.0, y: -100.0 }, Pair { x: 1.0, y: 0.0 }));
+        for _ in 0..3 {
+            world.step_teams(&[1]);
+        }
+        let xs: Vec<f32> = world.with_tanks(|tanks| tanks.iter().map(|t| t.read().unwrap().pos.x).collect());
+        assert_eq!(xs, vec![0.0, 3.0 * world.config.tank_v]);
+        // Bullets fly regardless
+        assert_eq!(world.with_bullets(|bullets| bullets[0].read().unwrap().pos.x), 3.0);
+        world.step();
+        let xs: Vec<f32> = world.with_tanks(|tanks| tanks.iter().map(|t| t.read().unwrap().pos.x).collect());
+        assert_eq!(xs, vec![1.0, 4.0]);
+    }
+
+    #[test]
+    fn cooldowns_can_be_read_back() {
+        let mut config = Configuration::default();
+        config.alter_cooldown = 3;
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        assert_eq!(tank.info().cooldowns[0], 0);
+        fire_upcall(&mut tank, &world);
+        assert_eq!(tank.info().cooldowns[0], 3);
+        // Each query is a step later, so the timer has run down by one more
+        for left in (0..3).rev() {
+            let slot = Arc::new(Mutex::new(None));
+            service(&mut tank, &world, Upcall::CooldownRemaining(slot.clone()));
+            assert_eq!(*slot.lock().unwrap(), Some(left));
+        }
+        assert_eq!(tank.info().cooldowns[0], 0);
+    }
+
+    #[test]
+    fn tanks_stop_flush_against_obstacles() {
+        let mut config = Configuration::default();
+        config.obstacles = vec![AABB::from_corners(Pair { x: 10.0, y: -10.0 }, Pair { x: 30.0, y: 10.0 })];
+        let mut world = config.build();
+        let tank = program_tank(&world, Module::new(forever(&call(0))).with_imports(&["forward"]));
+        world.add_tank(tank).unwrap();
+        for _ in 0..10 {
+            world.step();
+        }
+        let pos = world.with_tanks(|tanks| tanks[0].read().unwrap().pos);
+        assert_eq!(pos, Pair { x: 10.0 - world.config.tank_radius, y: 0.0 });
+    }
+
+    #[test]
+    fn explosion_damage_falls_off_with_distance() {
+        let mut config = Configuration::default();
+        config.explode_damage = Some(80);
+        let mut world = config.build();
+        for x in &[0.0, 25.0, 60.0] {
+            let mut tank = Tank::dummy(Pair { x: *x, y: 0.0 }, 0);
+            tank.health = 100;
+            world.add_tank(tank).unwrap();
+        }
+        let health = |world: &World| -> Vec<i32> {
+            world.with_tanks(|tanks| tanks.iter().map(|t| t.read().unwrap().health).collect())
+        };
+        world.explode(Pair::zero(), 50.0);
+        world.step();
+        assert_eq!(health(&world), vec![20, 60, 100]);
+        world.explode(Pair::zero(), 50.0);
+        world.step();
+        let tanks = world.tanks.read().unwrap();
+        let center = tanks[0].read().unwrap();
+        assert_eq!(center.state, TankState::Dead);
+        assert_eq!(center.death_cause, Some(DeathCause::Explosion));
+        assert_eq!(tanks[1].read().unwrap().health, 20);
+    }
+
+    #[test]
+    fn self_destruct_kills_the_bomber_whatever_its_health() {
+        let mut config = Configuration::default();
+        config.explode_damage = Some(80);
+        let mut world = config.build();
+        for x in &[0.0, 25.0] {
+            let mut tank = Tank::dummy(Pair { x: *x, y: 0.0 }, 0);
+            tank.health = 1000;
+            world.add_tank(tank).unwrap();
+        }
+        {
+            let tanks = world.tanks.read().unwrap();
+            service(&mut tanks[0].write().unwrap(), &world, Upcall::Explode);
+        }
+        world.step();
+        let tanks = world.tanks.read().unwrap();
+        let bomber = tanks[0].read().unwrap();
+        assert_eq!(bomber.state, TankState::Dead);
+        assert_eq!(bomber.death_cause, Some(DeathCause::SelfDestruct));
+        // The blast itself only hurts the others
+        assert_eq!(bomber.health, 1000);
+        assert_eq!(tanks[1].read().unwrap().health, 960);
+    }
+
+    #[test]
+    fn tanks_know_their_own_team_and_id() {
+        let world = world_of_dummies();
+        let tanks = world.tanks.read().unwrap();
+        let mut tank = tanks[3].write().unwrap();
+        let team = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::MyTeam(team.clone()));
+        assert_eq!(*team.lock().unwrap(), Some(1));
+        let id = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::MyId(id.clone()));
+        assert_eq!(*id.lock().unwrap(), Some(3));
+    }
+
+    fn drive_off_edge(boundary: BoundaryBehavior) -> Tank {
+        let mut config = Configuration::default();
+        config.tank_v = 2.0;
+        config.boundary = boundary;
+        let world = config.build();
+        // Its edge is one short of the arena's
+        let mut tank = Tank::dummy(Pair { x: 494.0, y: 0.0 }, 0);
+        tank.throttle = 1.0;
+        tank.step(&world);
+        tank
+    }
+
+    #[test]
+    fn arena_edges_behave_as_configured() {
+        let tank = drive_off_edge(BoundaryBehavior::Ignore);
+        assert_eq!((tank.pos, tank.vel), (Pair { x: 496.0, y: 0.0 }, Pair { x: 2.0, y: 0.0 }));
+        assert_eq!(drive_off_edge(BoundaryBehavior::Clamp).pos, Pair { x: 495.0, y: 0.0 });
+        let tank = drive_off_edge(BoundaryBehavior::Kill);
+        assert_eq!(tank.state, TankState::Dead);
+        assert_eq!(tank.death_cause, Some(DeathCause::Boundary));
+        let tank = drive_off_edge(BoundaryBehavior::Bounce);
+        assert_eq!((tank.pos, tank.vel), (Pair { x: 494.0, y: 0.0 }, Pair { x: -2.0, y: 0.0 }));
+        assert!((tank.angle - ::std::f32::consts::PI).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reset_worlds_start_over() {
+        let mut world = world_of_dummies();
+        let rx = world.subscribe();
+        world.add_bullet(Bullet::new(Pair::zero(), Pair { x: 1.0, y: 0.0 }));
+        world.step();
+        // Queued, but never set off
+        world.explode(Pair { x: 100.0, y: 100.0 }, 10.0);
+        world.reset();
+        assert!(world.tanks.read().unwrap().is_empty());
+        assert!(world.bullets.read().unwrap().is_empty());
+        assert_eq!(world.steps, 0);
+        assert_eq!(world.add_tank(Tank::dummy(Pair { x: 100.0, y: 100.0 }, 0)), Ok(0));
+        assert_eq!(world.add_bullet(Bullet::new(Pair { x: 50.0, y: 50.0 }, Pair::zero())), 0);
+        world.step();
+        assert!(world.effects().is_empty());
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().state, TankState::Halted);
+        // The subscriber carries over
+        world.add_tank(Tank::dummy(Pair { x: -100.0, y: 0.0 }, 1)).unwrap();
+        let mut shot = Bullet::new(Pair { x: -100.0, y: 3.0 }, Pair::zero());
+        shot.immune_steps = 0;
+        world.add_bullet(shot);
+        world.step();
+        match rx.try_iter().last() {
+            Some(GameEvent::Death { tank: 1, team: 1 }) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn worlds_tally_their_tanks_host_calls() {
+        let mut world = Configuration::default().build();
+        let tank = program_tank(&world, Module::new(forever(&call(0))).with_imports(&["yield"]));
+        world.add_tank(tank).unwrap();
+        for _ in 0..3 {
+            world.step();
+        }
+        assert_eq!(world.call_stats().upcalls, 3);
+        world.reset();
+        assert_eq!(world.call_stats(), CallStats::default());
+    }
+
+    #[test]
+    fn the_oldest_bullets_make_way_past_the_cap() {
+        let mut config = Configuration::default();
+        config.max_total_bullets = Some(3);
+        let mut world = config.build();
+        let bullet_ids = |world: &World| -> Vec<usize> {
+            world.with_bullets(|bullets| bullets.iter().map(|b| b.read().unwrap().id).collect())
+        };
+        let add = |world: &World, xs: &[f32]| {
+            for &x in xs {
+                world.add_bullet(Bullet::new(Pair { x, y: 0.0 }, Pair::zero()));
+            }
+        };
+        add(&world, &[0.0, 10.0]);
+        world.step();
+        add(&world, &[20.0, 30.0, 40.0]);
+        world.step();
+        assert_eq!(bullet_ids(&world), vec![2, 3, 4]);
+        // Ties in age go in the order they were fired
+        add(&world, &[50.0]);
+        world.step();
+        assert_eq!(bullet_ids(&world), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn entities_in_finds_what_is_in_the_area() {
+        let world = world_of_dummies();
+        world.tanks.read().unwrap()[1].write().unwrap().kill(DeathCause::Collision);
+        for x in &[160.0, 170.0, 0.0] {
+            world.add_bullet(Bullet::new(Pair { x: *x, y: 100.0 }, Pair::zero()));
+        }
+        world.bullets.read().unwrap()[1].write().unwrap().dead = true;
+        let area = AABB::from_corners(Pair { x: 120.0, y: 50.0 }, Pair { x: 260.0, y: 150.0 });
+        let found = |live_only: bool| -> (Vec<usize>, Vec<usize>) {
+            let (mut tanks, mut bullets) = (Vec::new(), Vec::new());
+            let entities = world.entities_in(area.clone(), live_only);
+            // Tanks come first
+            let first_bullet = entities.iter().position(|e| match e {
+                EntityRef::Bullet(_) => true,
+                _ => false,
+            });
+            for (i, e) in entities.iter().enumerate() {
+                match e {
+                    EntityRef::Tank(t) => {
+                        assert!(first_bullet.map_or(true, |b| i < b));
+                        tanks.push(t.read().unwrap().id);
+                    }
+                    EntityRef::Bullet(b) => bullets.push(b.read().unwrap().id),
+                }
+            }
+            tanks.sort();
+            bullets.sort();
+            (tanks, bullets)
+        };
+        assert_eq!(found(false), (vec![1, 2, 3], vec![0, 1]));
+        assert_eq!(found(true), (vec![2, 3], vec![0]));
+        assert!(Configuration::default().build().entities_in(area.clone(), false).is_empty());
+    }
+
+    #[test]
+    fn faster_shots_run_hotter() {
+        let mut config = Configuration::default();
+        config.heat_per_bullet_speed = 2.0;
+        config.bullet_v = 5.4;
+        let world = config.build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        assert_eq!(tank.fire(&world, BulletKind::Standard), FireStatus::Fired);
+        // 26 + round(2.0 * 5.4)
+        assert_eq!(tank.temp, 37);
+    }
+
+    #[test]
+    fn hottest_and_coldest_tanks_are_found() {
+        let world = world_of_dummies();
+        {
+            let tanks = world.tanks.read().unwrap();
+            for (t, temp) in tanks.iter().zip(&[50, 80, 80, 10]) {
+                t.write().unwrap().temp = *temp;
+            }
+            tanks[2].write().unwrap().pos = Pair { x: 200.0, y: 300.0 };
+        }
+        // Ties go to the lowest id
+        assert_eq!(world.hottest_tank(), Some((1, 80)));
+        assert_eq!(world.coldest_tank(), Some((1, 10)));
+        world.tanks.read().unwrap()[3].write().unwrap().kill(DeathCause::Collision);
+        assert_eq!(world.coldest_tank(), Some((0, 50)));
+
+        let from = Pair { x: 0.0, y: 100.0 };
+        let hottest = world.scan_hottest(from, 1, (-0.1, 1.0)).unwrap();
+        assert!((hottest - ::std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+        assert_eq!(world.scan_hottest(from, 1, (-0.1, 0.1)), Some(0.0));
+        assert_eq!(world.scan_hottest(from, 0, (-0.1, 0.1)), Some(0.0));
+        assert_eq!(world.scan_hottest(from, 0, (2.0, 3.0)), None);
+        assert_eq!(Configuration::default().build().hottest_tank(), None);
+    }
+
+    #[test]
+    fn freshly_spawned_tanks_are_protected() {
+        let mut config = Configuration::default();
+        config.spawn_protection = 2;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let hit = |world: &World| {
+            let mut shot = Bullet::new(Pair { x: 0.0, y: 3.0 }, Pair::zero());
+            shot.immune_steps = 0;
+            world.add_bullet(shot);
+        };
+        let state = |world: &World| -> (bool, TankState) {
+            let info = world.tanks.read().unwrap()[0].read().unwrap().info();
+            (info.protected, if info.dead { TankState::Dead } else { TankState::Halted })
+        };
+        assert_eq!(state(&world), (true, TankState::Halted));
+        hit(&world);
+        world.explode(Pair::zero(), 10.0);
+        world.step();
+        assert_eq!(state(&world), (true, TankState::Halted));
+        // The bullet is still used up
+        assert!(world.bullets.read().unwrap().is_empty());
+        hit(&world);
+        world.step();
+        assert_eq!(state(&world), (false, TankState::Halted));
+        hit(&world);
+        world.step();
+        assert_eq!(state(&world), (false, TankState::Dead));
+    }
+
+    #[test]
+    fn scans_answer_from_the_cache_during_their_cooldown() {
+        let mut config = Configuration::default();
+        config.scan_cooldown = 3;
+        let mut world = config.build();
+        for (i, team) in [0, 1, 0, 1].iter().enumerate() {
+            let pos = Pair { x: 100.0 + 50.0 * i as f32, y: 100.0 };
+            world.add_tank(Tank::dummy(pos, *team)).unwrap();
+        }
+        let tanks = world.tanks.read().unwrap();
+        let mut tank = tanks[0].write().unwrap();
+        let scan = |tank: &mut Tank| -> Option<u64> {
+            let slot = Arc::new(Mutex::new(None));
+            service(tank, &world, Upcall::Scan(-0.1, 0.1, slot.clone()));
+            let result = *slot.lock().unwrap();
+            result
+        };
+        assert_eq!(scan(&mut tank), Some((1 << 32) | 2));
+        tanks[1].write().unwrap().pos = Pair { x: 150.0, y: 300.0 };
+        assert_eq!(scan(&mut tank), Some((1 << 32) | 2));
+        // Other scans wait the cooldown out
+        let slot = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::ScanBullets(-0.1, 0.1, slot.clone()));
+        assert_eq!(*slot.lock().unwrap(), None);
+        tank.step(&world);
+        assert_eq!(*slot.lock().unwrap(), Some(0));
+        for _ in 0..3 {
+            tank.step(&world);
+        }
+        assert_eq!(scan(&mut tank), Some((1 << 32) | 1));
+    }
+
+    fn survivors_of_a_ram(tank_collisions: bool, bullet: bool) -> usize {
+        let mut config = Configuration::default();
+        config.tank_collisions = tank_collisions;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 8.0, y: 0.0 }, 1)).unwrap();
+        if bullet {
+            let mut shot = Bullet::new(Pair { x: 0.0, y: 3.0 }, Pair::zero());
+            shot.immune_steps = 0;
+            world.add_bullet(shot);
+        }
+        world.step();
+        let alive = world
+            .tanks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|t| t.read().unwrap().state != TankState::Dead)
+            .count();
+        alive
+    }
+
+    #[test]
+    fn tanks_pass_through_each_other_when_they_cannot_collide() {
+        assert_eq!(survivors_of_a_ram(true, false), 0);
+        assert_eq!(survivors_of_a_ram(false, false), 2);
+        // Bullets still land, even though the tanks they pass between are spared
+        assert_eq!(survivors_of_a_ram(false, true), 1);
+    }
+
+    #[test]
+    fn upcall_angles_can_be_in_degrees() {
+        assert_eq!(AngleUnit::Radians.to_radians(1.5), 1.5);
+        assert!((AngleUnit::Degrees.to_radians(180.0) - ::std::f32::consts::PI).abs() < 1e-5);
+        assert!((AngleUnit::Degrees.from_radians(::std::f32::consts::PI / 2.0) - 90.0).abs() < 1e-3);
+
+        let mut config = Configuration::default();
+        config.angle_unit = AngleUnit::Degrees;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 0.0, y: 100.0 }, 1)).unwrap();
+        let tanks = world.tanks.read().unwrap();
+        let mut tank = tanks[0].write().unwrap();
+
+        let slot = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::Scan(80.0, 100.0, slot.clone()));
+        assert_eq!(*slot.lock().unwrap(), Some(1));
+        // Bearings coming back are in degrees too
+        let slot = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::ScanHottest(80.0, 100.0, slot.clone()));
+        let bearing = slot.lock().unwrap().unwrap();
+        assert!((bearing - 90.0).abs() < 1e-3);
+
+        service(&mut tank, &world, Upcall::Aim(-90.0));
+        assert!((tank.aim - 1.5 * ::std::f32::consts::PI).abs() < 1e-5);
+        service(&mut tank, &world, Upcall::Turn(180.0));
+        assert!((tank.angle - ::std::f32::consts::PI).abs() < 1e-5);
+    }
+
+    fn crossed_bullets(bullet_collisions: bool) -> World {
+        let mut config = Configuration::default();
+        config.bullet_collisions = bullet_collisions;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 0)).unwrap();
+        for x in &[0.0, 1.0, 100.0] {
+            let mut bullet = Bullet::new(Pair { x: *x, y: 3.0 }, Pair::zero());
+            bullet.immune_steps = 0;
+            world.add_bullet(bullet);
+        }
+        world
+    }
+
+    #[test]
+    fn collisions_can_be_detected_without_resolving_them() {
+        let world = crossed_bullets(false);
+        let pairs = world.detect_collisions();
+        assert_eq!(pairs.len(), 1);
+        match pairs[0] {
+            (EntityRef::Tank(ref t), EntityRef::Bullet(ref b)) => {
+                assert_eq!(t.read().unwrap().id, 0);
+                assert_eq!(b.read().unwrap().pos.x, 100.0);
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+        // Nothing has been done about it yet
+        assert!(world.tanks.read().unwrap().iter().all(|t| t.read().unwrap().state != TankState::Dead));
+        assert!(world.bullets.read().unwrap().iter().all(|b| !b.read().unwrap().dead));
+
+        let world = crossed_bullets(true);
+        let pairs = world.detect_collisions();
+        assert_eq!(pairs.len(), 2);
+        match pairs[1] {
+            (EntityRef::Bullet(ref a), EntityRef::Bullet(ref b)) => {
+                let xs = (a.read().unwrap().pos.x, b.read().unwrap().pos.x);
+                assert_eq!(xs, (0.0, 1.0));
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    // How many steps a bullet fired point blank takes to hit the tank that fired it
+    fn steps_until_point_blank_hit(immunity: usize) -> usize {
+        let mut config = Configuration::default();
+        config.bullet_s = 0.0;
+        config.bullet_v = 0.0;
+        config.bullet_spawn_immunity = immunity;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let tank = world.tanks.read().unwrap()[0].clone();
+        tank.write().unwrap().fire(&world, BulletKind::Standard);
+        assert_eq!(world.bullets.read().unwrap()[0].read().unwrap().immune_steps, immunity);
+        for steps in 1..10 {
+            world.step();
+            if tank.read().unwrap().state == TankState::Dead {
+                return steps;
+            }
+        }
+        panic!("never hit");
+    }
+
+    #[test]
+    fn bullet_immunity_lasts_its_configured_collision_phases() {
+        assert_eq!(steps_until_point_blank_hit(0), 1);
+        assert_eq!(steps_until_point_blank_hit(1), 2);
+        assert_eq!(steps_until_point_blank_hit(4), 5);
+    }
+
+    #[test]
+    fn team_centroids_average_the_living() {
+        let world = world_of_dummies();
+        assert_eq!(world.team_centroid(0), Some(Pair { x: 150.0, y: 100.0 }));
+        assert_eq!(world.team_centroid(1), Some(Pair { x: 200.0, y: 100.0 }));
+        assert_eq!(world.team_centroid(7), None);
+        world.tanks.read().unwrap()[2].write().unwrap().state = TankState::Dead;
+        assert_eq!(world.team_centroid(0), Some(Pair { x: 100.0, y: 100.0 }));
+        for i in &[1, 3] {
+            world.tanks.read().unwrap()[*i].write().unwrap().state = TankState::Dead;
+        }
+        assert_eq!(world.team_centroid(1), None);
+
+        // A stepping tank counts itself, though it's locked at the time
+        world.tanks.read().unwrap()[2].write().unwrap().state = TankState::Halted;
+        let tanks = world.tanks.read().unwrap();
+        let mut tank = tanks[0].write().unwrap();
+        let slot = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::TeamCentroidX(slot.clone()));
+        assert_eq!(*slot.lock().unwrap(), Some(150.0));
+        let slot = Arc::new(Mutex::new(None));
+        service(&mut tank, &world, Upcall::TeamCentroidY(slot.clone()));
+        assert_eq!(*slot.lock().unwrap(), Some(100.0));
+    }
+
+    #[test]
+    fn worlds_turn_tanks_away_past_the_cap() {
+        let mut config = Configuration::default();
+        config.max_tanks = Some(2);
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 50.0, y: 0.0 }, 1)).unwrap();
+        let third = world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 0));
+        assert_eq!(third, Err(SpawnError::TooManyTanks(2)));
+        assert_eq!(world.tanks.read().unwrap().len(), 2);
+        // Dead tanks don't count against it
+        world.tanks.read().unwrap()[0].write().unwrap().state = TankState::Dead;
+        assert!(world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 0)).is_ok());
+        assert_eq!(world.tanks.read().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn tanks_and_bullets_can_be_read_through_a_poisoned_lock() {
+        let world = world_of_dummies();
+        world.add_bullet(Bullet::new(Pair::zero(), Pair::zero()));
+        assert_eq!(world.with_tanks(|tanks| tanks.len()), 4);
+        assert_eq!(world.with_bullets(|bullets| bullets.len()), 1);
+        let poisoned = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let _tanks = world.tanks.write().unwrap();
+            let _bullets = world.bullets.write().unwrap();
+            panic!("poisoning the lists");
+        }));
+        assert!(poisoned.is_err());
+        assert!(world.tanks.is_poisoned() && world.bullets.is_poisoned());
+        let x = world.with_tanks(|tanks| tanks[3].read().unwrap().pos.x);
+        assert_eq!(x, 250.0);
+        assert_eq!(world.with_bullets(|bullets| bullets.len()), 1);
+    }
+
+    #[test]
+    fn aim_assist_snaps_shots_onto_nearby_enemies() {
+        let mut config = Configuration::default();
+        config.aim_assist_tolerance = Some(0.2);
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 20.0, y: 0.0 }, 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 10.0 }, 1)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 50.0, y: -5.0 }, 1)).unwrap();
+        let near = Pair { x: 50.0, y: -5.0 }.ang();
+        let far = Pair { x: 100.0, y: 10.0 }.ang();
+        // The nearest enemy in the cone wins; teammates are never picked
+        assert_eq!(world.assist_bearing(Pair::zero(), 0, 0.0, 0.2), Some(near));
+        assert_eq!(world.assist_bearing(Pair::zero(), 0, 0.1, 0.05), Some(far));
+        assert_eq!(world.assist_bearing(Pair::zero(), 0, 0.0, 0.05), None);
+        assert_eq!(world.assist_bearing(Pair::zero(), 0, ::std::f32::consts::PI, 0.2), None);
+
+        let tank = world.tanks.read().unwrap()[0].clone();
+        tank.write().unwrap().fire(&world, BulletKind::Standard);
+        let heading = world.bullets.read().unwrap()[0].read().unwrap().vel.ang();
+        assert!((heading - near).abs() < 1e-4);
+    }
+
+    fn scan_into_program(ptr: i32) -> Module {
+        let mut body = f32_const(-0.1);
+        body.extend(f32_const(0.1));
+        body.extend(i32_const(ptr));
+        body.extend(call(0));
+        Module::new(body).with_imports(&["scan_into"])
+    }
+
+    #[test]
+    fn scans_can_be_written_into_memory() {
+        let mut world = Configuration::default().build();
+        let tank = program_tank(&world, scan_into_program(64));
+        world.add_tank(tank).unwrap();
+        for (x, y, team) in &[(100.0, 0.0, 0), (200.0, 0.0, 1), (300.0, 0.0, 1), (0.0, 100.0, 1)] {
+            world.add_tank(Tank::dummy(Pair { x: *x, y: *y }, *team)).unwrap();
+        }
+        world.step();
+        let tank = world.tanks.read().unwrap()[0].clone();
+        let tank = tank.read().unwrap();
+        let bytes = tank.vm.as_ref().unwrap().read_bytes(64, 8).unwrap();
+        assert_eq!(bytes, vec![1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(tank.scan_cache, Some((1 << 32) | 2));
+
+        // A pointer the result wouldn't fit behind is caught when the call is made
+        let mut world = Configuration::default().build();
+        let tank = program_tank(&world, scan_into_program(65533));
+        world.add_tank(tank).unwrap();
+        world.step();
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().state, TankState::Dead);
+    }
+
+    #[test]
+    fn dead_tanks_carry_nothing_out() {
+        let mut world = world_of_dummies();
+        {
+            let tanks = world.tanks.read().unwrap();
+            let mut tank = tanks[0].write().unwrap();
+            // Something left pending from before it died stays undone
+            tank.state = TankState::Pending(Upcall::Fire(Arc::new(Mutex::new(None))));
+            tank.throttle = 1.0;
+            tank.arming = Some(1);
+            tank.kill(DeathCause::Collision);
+            assert_eq!((tank.throttle, tank.arming), (0.0, None));
+            // Only the first cause of death is kept
+            tank.kill(DeathCause::Explosion);
+            assert_eq!(tank.death_cause, Some(DeathCause::Collision));
+        }
+        for _ in 0..3 {
+            world.step();
+        }
+        assert!(world.bullets.read().unwrap().is_empty());
+        let living = world.with_tanks(|tanks| {
+            tanks.iter().filter(|t| t.read().unwrap().state != TankState::Dead).count()
+        });
+        assert_eq!(living, 3);
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().pos, Pair { x: 100.0, y: 100.0 });
+    }
+
+    fn hits_from(vel: Pair, hit_requires_closing: bool) -> usize {
+        let mut config = Configuration::default();
+        config.hit_requires_closing = hit_requires_closing;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let mut bullet = Bullet::new(Pair { x: 3.0, y: 0.0 }, vel);
+        bullet.immune_steps = 0;
+        world.add_bullet(bullet);
+        world.detect_collisions().len()
+    }
+
+    #[test]
+    fn only_closing_bullets_hit_when_required() {
+        let away = Pair { x: 1.0, y: 0.0 };
+        let toward = Pair { x: -1.0, y: 0.0 };
+        assert_eq!(hits_from(away, false), 1);
+        assert_eq!(hits_from(away, true), 0);
+        assert_eq!(hits_from(toward, true), 1);
+        // Passing by at right angles isn't closing either
+        assert_eq!(hits_from(Pair { x: 0.0, y: 1.0 }, true), 0);
+    }
+
+    #[test]
+    fn tanks_share_the_world_config() {
+        let mut world = Configuration::default().build();
+        assert_eq!(Arc::strong_count(&world.config), 1);
+        let tank = program_tank(&world, Module::new(vec![NOP]));
+        assert_eq!(Arc::strong_count(&world.config), 2);
+        world.add_tank(tank).unwrap();
+        // Dummies have no program, so nothing to share it with
+        world.add_tank(Tank::dummy(Pair { x: 50.0, y: 0.0 }, 1)).unwrap();
+        assert_eq!(Arc::strong_count(&world.config), 2);
+        world.tanks.write().unwrap().clear();
+        assert_eq!(Arc::strong_count(&world.config), 1);
+    }
+
+    #[test]
+    fn tanks_can_find_the_nearest_of_a_team() {
+        let world = world_of_dummies();
+        let (dist, bearing) = world.nearest_on_team(Pair { x: 300.0, y: 100.0 }, 0).unwrap();
+        assert_eq!(dist, 100.0);
+        assert!((bearing - ::std::f32::consts::PI).abs() < 1e-6);
+        assert_eq!(world.nearest_on_team(Pair::zero(), 9), None);
+
+        let tanks = world.tanks.read().unwrap();
+        let mut tank = tanks[0].write().unwrap();
+        let ask = |tank: &mut Tank, upcall: fn(i32, Arc<Mutex<Option<f32>>>) -> Upcall, team: i32| {
+            let slot = Arc::new(Mutex::new(None));
+            service(tank, &world, upcall(team, slot.clone()));
+            let answer = slot.lock().unwrap().unwrap();
+            answer
+        };
+        assert_eq!(ask(&mut tank, Upcall::DistToTeam, 1), 50.0);
+        assert_eq!(ask(&mut tank, Upcall::BearingToTeam, 1), 0.0);
+        // The asking tank doesn't count as its own nearest teammate
+        assert_eq!(ask(&mut tank, Upcall::DistToTeam, 0), 100.0);
+        assert_eq!(ask(&mut tank, Upcall::DistToTeam, 9), -1.0);
+        assert_eq!(ask(&mut tank, Upcall::BearingToTeam, -1), -1.0);
+    }
+
+    #[test]
+    fn sudden_death_ramps_up_the_idle_heat() {
+        let mut config = Configuration::default();
+        config.sudden_death_after = Some(2);
+        config.sudden_death_heat_ramp = 10;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            seen.push((world.in_sudden_death(), world.idle_heat()));
+            world.step();
+        }
+        assert_eq!(seen, vec![(false, -2), (false, -2), (true, 8), (true, 18)]);
+        assert_eq!(world.tanks.read().unwrap()[0].read().unwrap().temp, 26);
+        // Without it, idle heat stays as configured
+        let mut world = Configuration::default().build();
+        world.steps = 1000;
+        assert_eq!((world.in_sudden_death(), world.idle_heat()), (false, -2));
+    }
+
+    // The step on which a tank running `module` is killed for not making upcalls, if any
+    fn unresponsive_after(module: Module) -> Option<usize> {
+        let mut config = Configuration::default();
+        config.idle_timeout_steps = Some(3);
+        let mut world = config.build();
+        let tank = program_tank(&world, module);
+        world.add_tank(tank).unwrap();
+        for step in 1..10 {
+            world.step();
+            let tank = world.tanks.read().unwrap()[0].clone();
+            let tank = tank.read().unwrap();
+            if tank.state == TankState::Dead {
+                assert_eq!(tank.death_cause, Some(DeathCause::Unresponsive));
+                return Some(step);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn tanks_stuck_in_loops_time_out() {
+        assert_eq!(unresponsive_after(Module::new(forever(&[NOP]))), Some(3));
+        // Yielding, or having nothing left to run, is fine
+        assert_eq!(unresponsive_after(Module::new(forever(&call(0))).with_imports(&["yield"])), None);
+        assert_eq!(unresponsive_after(Module::new(vec![NOP])), None);
+    }
+
+    #[test]
+    fn heavy_bullets_are_bigger_and_hit_harder() {
+        assert_eq!(BulletKind::from_i32(1), Some(BulletKind::Heavy));
+        assert_eq!(BulletKind::from_i32(3), None);
+        let mut config = Configuration::default();
+        config.collision = CollisionRule::Damage;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.tanks.read().unwrap()[0].write().unwrap().health = 100;
+        // Just out of reach of a standard bullet, but not of a heavy one
+        for kind in &[BulletKind::Standard, BulletKind::Heavy] {
+            let mut bullet = Bullet::new(Pair { x: 7.5, y: 0.0 }, Pair::zero());
+            bullet.kind = *kind;
+            bullet.immune_steps = 0;
+            world.add_bullet(bullet);
+        }
+        world.step();
+        let health = world.tanks.read().unwrap()[0].read().unwrap().health;
+        assert_eq!(health, 100 - 60);
+        let kinds: Vec<BulletKind> =
+            world.with_bullets(|bullets| bullets.iter().map(|b| b.read().unwrap().kind).collect());
+        assert_eq!(kinds, vec![BulletKind::Standard]);
+    }
+
+    #[test]
+    fn scatter_bullets_split_into_a_fan() {
+        let mut config = Configuration::default();
+        config.scatter_age = 2;
+        config.scatter_count = 3;
+        config.scatter_spread = 0.4;
+        let mut world = config.build();
+        let mut bullet = Bullet::new(Pair::zero(), Pair { x: 5.0, y: 0.0 });
+        bullet.kind = BulletKind::Scatter;
+        world.add_bullet(bullet);
+        world.step();
+        assert_eq!(world.bullets.read().unwrap().len(), 1);
+        world.step();
+        let bullets = world.bullets.read().unwrap();
+        assert_eq!(bullets.len(), 3);
+        for (b, heading) in bullets.iter().zip(&[-0.4, 0.0, 0.4]) {
+            let b = b.read().unwrap();
+            assert_eq!(b.kind, BulletKind::Standard);
+            assert!(b.pos.approx_eq(&Pair { x: 10.0, y: 0.0 }, 1e-4));
+            assert!(b.vel.approx_eq(&(Pair::polar(*heading) * 5.0), 1e-4));
+        }
+    }
+
+    #[test]
+    fn status_sums_up_the_match() {
+        let mut world = world_of_dummies();
+        world.add_bullet(Bullet::new(Pair { x: 0.0, y: -100.0 }, Pair::zero()));
+        for i in &[1, 3] {
+            world.tanks.read().unwrap()[*i].write().unwrap().state = TankState::Dead;
+        }
+        world.step();
+        let status = world.status();
+        assert_eq!(status.step, 1);
+        // Teams with nobody left still show up
+        assert_eq!(status.living.into_iter().collect::<Vec<_>>(), vec![(0, 2), (1, 0)]);
+        assert_eq!((status.tanks, status.bullets), (4, 1));
+        assert!(status.finished);
+    }
+
+    #[test]
+    fn zones_stop_fire_and_cool_tanks() {
+        let no_fire = Zone {
+            shape: ZoneShape::Rect(AABB::around(Pair::zero(), Pair::both(20.0))),
+            effect: ZoneEffect::NoFire,
+        };
+        let heal = Zone {
+            shape: ZoneShape::Circle { center: Pair { x: 100.0, y: 0.0 }, radius: 10.0 },
+            effect: ZoneEffect::Heal { rate: 5 },
+        };
+        assert!(no_fire.contains(Pair { x: 9.0, y: -9.0 }) && !no_fire.contains(Pair { x: 11.0, y: 0.0 }));
+        assert!(heal.contains(Pair { x: 95.0, y: 5.0 }) && !heal.contains(Pair { x: 110.0, y: 0.0 }));
+        let mut config = Configuration::default();
+        config.zones = vec![no_fire, heal];
+        let world = config.build();
+        assert_eq!(world.zones_at(Pair { x: 105.0, y: 0.0 }).count(), 1);
+        assert_eq!(world.zones_at(Pair { x: 50.0, y: 0.0 }).count(), 0);
+
+        let mut inside = Tank::dummy(Pair { x: 5.0, y: 5.0 }, 0);
+        assert_eq!(fire_upcall(&mut inside, &world), FireStatus::NoFireZone as i32);
+        assert!(world.bullets.read().unwrap().is_empty());
+        let mut outside = Tank::dummy(Pair { x: 50.0, y: 0.0 }, 0);
+        assert_eq!(fire_upcall(&mut outside, &world), FireStatus::Fired as i32);
+
+        // Heal zones take their rate off on top of idle heat
+        let mut cooling = Tank::dummy(Pair { x: 100.0, y: 0.0 }, 0);
+        cooling.temp = 20;
+        cooling.step(&world);
+        assert_eq!(cooling.temp, 20 - 2 - 5);
+    }
+
+    // Where a bullet fired on the first step is at the end of it
+    fn first_bullet_x(order: StepOrder) -> f32 {
+        let mut config = Configuration::default();
+        config.step_order = order;
+        let mut world = config.build();
+        let tank = program_tank(&world, Module::new([call(0), vec![DROP]].concat()).with_imports(&["fire"]));
+        world.add_tank(tank).unwrap();
+        world.step();
+        let x = world.bullets.read().unwrap()[0].read().unwrap().pos.x;
+        x
+    }
+
+    #[test]
+    fn step_order_decides_whether_new_bullets_move_at_once() {
+        let muzzle = Configuration::default().bullet_s;
+        assert_eq!(first_bullet_x(StepOrder::BulletsFirst), muzzle);
+        assert_eq!(first_bullet_x(StepOrder::TanksFirst), muzzle + Configuration::default().bullet_v);
+    }
+
+    #[test]
+    fn tanks_hear_about_enemy_scans_a_step_later() {
+        let mut world = world_of_dummies();
+        {
+            let tanks = world.tanks.read().unwrap();
+            let mut tank = tanks[0].write().unwrap();
+            service(&mut tank, &world, Upcall::Scan(-0.1, 0.1, Arc::new(Mutex::new(None))));
+            service(&mut tank, &world, Upcall::Scan(-0.1, 0.1, Arc::new(Mutex::new(None))));
+        }
+        let counts = |world: &World| -> Vec<(usize, usize)> {
+            world.with_tanks(|tanks| {
+                tanks
+                    .iter()
+                    .map(|t| {
+                        let t = t.read().unwrap();
+                        (t.scans, t.scanned)
+                    })
+                    .collect()
+            })
+        };
+        // Teammates aren't counted
+        assert_eq!(counts(&world), vec![(0, 0), (2, 0), (0, 0), (2, 0)]);
+        world.step();
+        assert_eq!(counts(&world), vec![(0, 0), (0, 2), (0, 0), (0, 2)]);
+        let was_scanned = |world: &World, i: usize| {
+            let tanks = world.tanks.read().unwrap();
+            let mut tank = tanks[i].write().unwrap();
+            let slot = Arc::new(Mutex::new(None));
+            service(&mut tank, world, Upcall::WasScanned(slot.clone()));
+            let scanned = slot.lock().unwrap().unwrap();
+            scanned
+        };
+        assert_eq!((was_scanned(&world, 2), was_scanned(&world, 3)), (0, 2));
+        world.step();
+        assert_eq!(was_scanned(&world, 3), 0);
+    }
+
+    fn rams_between(teams: (Team, Team), friendly_collision: bool) -> usize {
+        let mut config = Configuration::default();
+        config.friendly_collision = friendly_collision;
+        let mut world = config.build();
+        world.add_tank(Tank::dummy(Pair::zero(), teams.0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 8.0, y: 0.0 }, teams.1)).unwrap();
+        world.detect_collisions().len()
+    }
+
+    #[test]
+    fn teammates_can_pass_through_each_other() {
+        assert_eq!(rams_between((0, 0), true), 1);
+        assert_eq!(rams_between((0, 0), false), 0);
+        // Enemies still collide
+        assert_eq!(rams_between((0, 1), false), 1);
+    }
+
+    #[test]
+    fn only_colliding_entities_are_handed_out() {
+        for &threshold in &[0, 100] {
+            let mut config = Configuration::default();
+            config.quadtree_threshold = threshold;
+            let mut world = config.build();
+            for x in &[0.0, 8.0, 200.0] {
+                world.add_tank(Tank::dummy(Pair { x: *x, y: 0.0 }, 0)).unwrap();
+            }
+            let mut bullet = Bullet::new(Pair { x: -100.0, y: 0.0 }, Pair::zero());
+            bullet.immune_steps = 0;
+            world.add_bullet(bullet);
+            let pairs = world.detect_collisions();
+            assert_eq!(pairs.len(), 1);
+            match pairs[0] {
+                (EntityRef::Tank(ref a), EntityRef::Tank(ref b)) => {
+                    assert_eq!((a.read().unwrap().pos.x, b.read().unwrap().pos.x), (0.0, 8.0));
+                }
+                ref other => panic!("unexpected {:?}", other),
+            }
+            // Nothing else was cloned out, or is still held
+            for (t, &expected) in world.tanks.read().unwrap().iter().zip(&[2, 2, 1]) {
+                assert_eq!(Arc::strong_count(t), expected);
+            }
+            assert_eq!(Arc::strong_count(&world.bullets.read().unwrap()[0]), 1);
+        }
+    }
+
+    #[test]
+    fn scans_only_reach_so_far() {
+        let from = Pair { x: 90.0, y: 100.0 };
+        let world = world_of_dummies();
+        assert_eq!(world.scan(from, 0, (-0.1, 0.1)), (2, 2));
+        let mut config = Configuration::default();
+        config.scan_range = Some(60.0);
+        let mut world = config.build();
+        for (i, team) in [0, 1, 0, 1].iter().enumerate() {
+            let pos = Pair { x: 100.0 + 50.0 * i as f32, y: 100.0 };
+            world.add_tank(Tank::dummy(pos, *team)).unwrap();
+        }
+        // Exactly at the limit still counts
+        assert_eq!(world.scan(from, 0, (-0.1, 0.1)), (1, 1));
+        assert_eq!(world.scan_hottest(from, 0, (-0.1, 0.1)), Some(0.0));
+        world.tanks.read().unwrap()[1].write().unwrap().state = TankState::Dead;
+        assert_eq!(world.scan_hottest(from, 0, (-0.1, 0.1)), None);
+    }
+
+    #[test]
+    fn tank_info_shows_what_a_tank_is_waiting_on() {
+        let mut world = Configuration::default().build();
+        let tank = program_tank(&world, Module::new(forever(&call(0))).with_imports(&["forward"]));
+        world.add_tank(tank).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 1)).unwrap();
+        // The second move of the step waits out the cooldown
+        world.step();
+        let tanks = world.tanks.read().unwrap();
+        let (mover, dummy) = (tanks[0].read().unwrap().info(), tanks[1].read().unwrap().info());
+        assert_eq!(mover.pending, Some("move forward".to_string()));
+        assert_eq!(dummy.pending, None);
+        let json = serde_json::to_value(&mover).unwrap();
+        assert_eq!(json["pending"], "move forward");
+        assert!(serde_json::to_value(&dummy).unwrap().get("pending").is_none());
+    }
+
+    #[test]
+    fn faulting_programs_blow_up_at_once() {
+        let mut config = Configuration::default();
+        config.alter_cooldown = 3;
+        config.explode_arm_delay = 5;
+        let mut world = config.build();
+        // The move leaves the alter timer running when the program traps
+        let body = [call(0), vec![UNREACHABLE]].concat();
+        let tank = program_tank(&world, Module::new(body).with_imports(&["forward"]));
+        world.add_tank(tank).unwrap();
+        world.step();
+        let tanks = world.tanks.read().unwrap();
+        let tank = tanks[0].read().unwrap();
+        assert_eq!(tank.state, TankState::Dead);
+        assert_eq!((tank.death_cause, tank.arming), (Some(DeathCause::Fault), None));
+        // The blast went off that same step
+        match &world.effects()[..] {
+            [Effect::Explosion { .. }] => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn simultaneous_scatter_splits_are_order_independent() {
+        let mut config = Configuration::default();
+        config.scatter_age = 1;
+        config.scatter_count = 3;
+        let mut world = config.build();
+        for &y in &[0.0, 50.0] {
+            let mut bullet = Bullet::new(Pair { x: 0.0, y }, Pair { x: 5.0, y: 0.0 });
+            bullet.kind = BulletKind::Scatter;
+            world.add_bullet(bullet);
+        }
+        // Both split on the first step; the pieces must come out with the same ids either way
+        world.assert_deterministic_step();
+        assert_eq!(world.bullets.read().unwrap().len(), 6);
+        world.assert_deterministic_step();
+    }
+
+    #[test]
+    fn throttle_and_forward_do_not_stack() {
+        let world = Configuration::default().build();
+        let mut tank = Tank::dummy(Pair::zero(), 0);
+        let v = world.config.tank_v;
+        service(&mut tank, &world, Upcall::SetThrottle(1.0));
+        assert_eq!(tank.pos, Pair { x: v, y: 0.0 });
+        service(&mut tank, &world, Upcall::Forward);
+        assert_eq!(tank.pos, Pair { x: 2.0 * v, y: 0.0 });
+        // Without a Forward, the throttle carries on alone
+        tank.step(&world);
+        assert_eq!(tank.pos, Pair { x: 3.0 * v, y: 0.0 });
+    }
+
+    #[test]
+    fn sudden_death_ends_a_match_of_idlers() {
+        let mut config = Configuration::default();
+        config.sudden_death_after = Some(2);
+        config.sudden_death_heat_ramp = 10;
+        let mut world = config.build();
+        for &(x, team) in &[(-200.0, 0), (200.0, 1)] {
+            let module = Module::new(forever(&call(0))).with_imports(&["yield"]);
+            let pos = Pair { x, y: 0.0 };
+            let tank = TankBuilder::new(pos, team, module.build(), world.config.clone())
+                .build()
+                .unwrap();
+            world.add_tank(tank).unwrap();
+        }
+        for _ in 0..50 {
+            if world.finished() {
+                break;
+            }
+            world.step();
+        }
+        assert!(world.finished(), "still going after {} steps", world.steps);
+        for t in world.tanks.read().unwrap().iter() {
+            assert_eq!(t.read().unwrap().death_cause, Some(DeathCause::Overheat));
+        }
+    }
+
+    #[test]
+    fn overlapping_blasts_kill_the_same_tanks_in_either_order() {
+        let dead_after = |blasts: &[Pair]| -> Vec<usize> {
+            let mut config = Configuration::default();
+            config.explode_damage = Some(80);
+            let mut world = config.build();
+            for x in &[0.0, 40.0, 80.0, 120.0] {
+                let mut tank = Tank::dummy(Pair { x: *x, y: 0.0 }, 0);
+                tank.health = 90;
+                world.add_tank(tank).unwrap();
+            }
+            for pos in blasts {
+                world.explode(*pos, 50.0);
+            }
+            world.step();
+            let tanks = world.tanks.read().unwrap();
+            let dead = tanks
+                .iter()
+                .map(|t| t.read().unwrap())
+                .filter(|t| t.state == TankState::Dead)
+                .map(|t| t.id)
+                .collect();
+            dead
+        };
+        let (a, b) = (Pair { x: 30.0, y: 0.0 }, Pair { x: 70.0, y: 0.0 });
+        // Only the tank caught in both blasts takes enough to die
+        assert_eq!(dead_after(&[a, b]), vec![1]);
+        assert_eq!(dead_after(&[b, a]), vec![1]);
+    }
+
+    #[test]
+    fn fuzzed_programs_and_answers_never_panic() {
+        // Host calls a fuzzed program may make: name, parameter types (f32, i32 or i64), and
+        // whether there's a result to drop
+        const CALLS: &[(&str, &str, bool)] = &[
+            ("scan", "ff", true),
+            ("scan_into", "ffi", false),
+            ("scan_bullets", "ff", true),
+            ("scan_hottest", "ff", true),
+            ("fire", "", true),
+            ("fire_kind", "i", true),
+            ("aim", "f", false),
+            ("turn", "f", false),
+            ("gpsx", "", true),
+            ("gpsy", "", true),
+            ("temp", "", true),
+            ("cooldown_remaining", "", true),
+            ("was_scanned", "", true),
+            ("my_team", "", true),
+            ("my_id", "", true),
+            ("team_centroid_x", "", true),
+            ("bearing_to_team", "i", true),
+            ("dist_to_team", "i", true),
+            ("forward", "", false),
+            ("set_throttle", "f", false),
+            ("set_label", "ii", false),
+            ("post_string", "i", false),
+            ("post_uint64", "l", false),
+            ("explode", "", false),
+            ("yield", "", false),
+        ];
+        let world = Configuration::default().build();
+        let mut rng = Rng::new(443);
+        for round in 0..200 {
+            // Pure noise never gets as far as a VM
+            let noise: Vec<u8> = (0..64).map(|_| rng.next_u64() as u8).collect();
+            assert!(VM::new(noise, world.config.clone(), &world.config.import_namespace).is_err());
+
+            let (mut names, mut body) = (Vec::new(), Vec::new());
+            for i in 0..4 {
+                let (name, params, result) = CALLS[(rng.next_u64() % CALLS.len() as u64) as usize];
+                for param in params.chars() {
+                    body.extend(match param {
+                        'f' => f32_const(f32::from_bits(rng.next_u64() as u32)),
+                        'i' => i32_const(rng.next_u64() as i32),
+                        _ => i64_const(rng.next_u64() as i64),
+                    });
+                }
+                body.extend(call(i));
+                if result {
+                    body.push(DROP);
+                }
+                names.push(name);
+            }
+            let mut program = Module::new(forever(&body)).with_imports(&names).build();
+            // Every other program gets a byte clobbered; it may or may not load after that
+            if round % 2 == 1 {
+                let at = (rng.next_u64() % program.len() as u64) as usize;
+                program[at] = rng.next_u64() as u8;
+            }
+            let built = TankBuilder::new(Pair::zero(), 0, program, world.config.clone()).build();
+            let mut tank = match built {
+                Ok(tank) => tank,
+                Err(_) => continue,
+            };
+            for _ in 0..5 {
+                tank.step_fuzz(&world, &mut rng);
+            }
+        }
     }
 }
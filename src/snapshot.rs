@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use sim::{Effect, TankInfo, World};
+use space::Pair;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BulletInfo {
+    pub id: usize,
+    pub pos: Pair,
+    pub vel: Pair,
+}
+
+// The externally visible state of a world at a given step. Tanks and bullets are kept in id
+// order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub step: usize,
+    pub tanks: Vec<TankInfo>,
+    pub bullets: Vec<BulletInfo>,
+}
+
+// Everything needed to bring a snapshot up to date with a later one: entities that are new or
+// changed (in full), ids of entities that are gone, and that step's effects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldDiff {
+    pub step: usize,
+    pub tanks: Vec<TankInfo>,
+    pub removed_tanks: Vec<usize>,
+    pub bullets: Vec<BulletInfo>,
+    pub removed_bullets: Vec<usize>,
+    pub effects: Vec<Effect>,
+}
+
+fn changes<T, F>(old: &[T], new: &[T], id: F) -> (Vec<T>, Vec<usize>)
+where
+    T: Clone + PartialEq,
+    F: Fn(&T) -> usize,
+{
+    let live: HashSet<usize> = new.iter().map(&id).collect();
+    let changed = new
+        .iter()
+        .filter(|n| !old.iter().any(|o| o == *n))
+        .cloned()
+        .collect();
+    let removed = old
+        .iter()
+        .map(&id)
+        .filter(|i| !live.contains(i))
+        .collect();
+    (changed, removed)
+}
+
+fn patch<T, F>(items: &mut Vec<T>, changed: &[T], removed: &[usize], id: F)
+where
+    T: Clone,
+    F: Fn(&T) -> usize,
+{
+    items.retain(|i| !removed.contains(&id(i)));
+    for c in changed {
+        match items.iter().position(|i| id(i) == id(c)) {
+            Some(idx) => items[idx] = c.clone(),
+            None => items.push(c.clone()),
+        }
+    }
+    items.sort_by_key(|i| id(i));
+}
+
+// 64-bit FNV-1a; simple, and stable across platforms and runs, unlike std's DefaultHasher.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.bytes(&v.to_bits().to_le_bytes());
+    }
+}
+
+impl WorldSnapshot {
+    pub fn apply(&mut self, diff: &WorldDiff) {
+        self.step = diff.step;
+        patch(&mut self.tanks, &diff.tanks, &diff.removed_tanks, |t| t.id);
+        patch(&mut self.bullets, &diff.bullets, &diff.removed_bullets, |b| b.id);
+    }
+}
+
+impl World {
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            step: self.steps,
+            tanks: self
+                .tanks
+                .read()
+                .unwrap()
+                .iter()
+                .map(|t| t.read().unwrap().info())
+                .collect(),
+            bullets: self
+                .bullets
+                .read()
+                .unwrap()
+                .iter()
+                .map(|b| {
+                    let b = b.read().unwrap();
+                    BulletInfo {
+                        id: b.id,
+                        pos: b.pos,
+                        vel: b.vel,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    // A fingerprint of the world's state: every tank's position, angles, temperature and health,
+    // and every bullet's position, in id order. Two runs that agree on this every step haven't
+    // diverged.
+    pub fn state_hash(&self) -> u64 {
+        let snapshot = self.snapshot();
+        let mut h = Fnv::new();
+        h.u64(snapshot.step as u64);
+        let mut tanks: Vec<&TankInfo> = snapshot.tanks.iter().collect();
+        tanks.sort_by_key(|t| t.id);
+        for t in tanks {
+            h.u64(t.id as u64);
+            h.f32(t.pos.x);
+            h.f32(t.pos.y);
+            h.f32(t.angle);
+            h.f32(t.aim);
+            h.u64(t.temp as u64);
+            h.u64(t.health as u64);
+            h.u64(t.dead as u64);
+        }
+        let mut bullets: Vec<&BulletInfo> = snapshot.bullets.iter().collect();
+        bullets.sort_by_key(|b| b.id);
+        for b in bullets {
+            h.u64(b.id as u64);
+            h.f32(b.pos.x);
+            h.f32(b.pos.y);
+        }
+        h.0
+    }
+
+    pub fn diff(&self, since: &WorldSnapshot) -> WorldDiff {
+        let now = self.snapshot();
+        let (tanks, removed_tanks) = changes(&since.tanks, &now.tanks, |t| t.id);
+        let (bullets, removed_bullets) = changes(&since.bullets, &now.bullets, |b| b.id);
+        WorldDiff {
+            step: now.step,
+            tanks,
+            removed_tanks,
+            bullets,
+            removed_bullets,
+            effects: self.effects().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sim::{Bullet, Configuration, Tank};
+
+    #[test]
+    fn diffs_bring_snapshots_up_to_date() {
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 1)).unwrap();
+        world.add_bullet(Bullet::new(Pair { x: 0.0, y: 50.0 }, Pair { x: 1.0, y: 0.0 }));
+        let mut doomed = Bullet::new(Pair { x: 100.0, y: 10.0 }, Pair { x: 0.0, y: -5.0 });
+        doomed.immune_steps = 0;
+        world.add_bullet(doomed);
+        let mut seen = world.snapshot();
+        world.step();
+        let diff = world.diff(&seen);
+        // Only what moved, died or went away is sent
+        assert_eq!(diff.step, 1);
+        assert_eq!(diff.tanks.iter().map(|t| t.id).collect::<Vec<_>>(), vec![1]);
+        assert!(diff.removed_tanks.is_empty());
+        assert_eq!(diff.bullets.iter().map(|b| b.id).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(diff.removed_bullets, vec![1]);
+        seen.apply(&diff);
+        assert_eq!(seen, world.snapshot());
+        // Nothing happening is an empty diff
+        let diff = world.diff(&seen);
+        assert!(diff.tanks.is_empty() && diff.removed_tanks.is_empty());
+    }
+
+    #[test]
+    fn fnv_matches_the_reference_values() {
+        let mut h = Fnv::new();
+        assert_eq!(h.0, 0xcbf29ce484222325);
+        h.bytes(b"a");
+        assert_eq!(h.0, 0xaf63dc4c8601ec8c);
+    }
+
+    fn hashed_world() -> World {
+        let mut world = Configuration::default().build();
+        world.add_tank(Tank::dummy(Pair::zero(), 0)).unwrap();
+        world.add_tank(Tank::dummy(Pair { x: 100.0, y: 0.0 }, 1)).unwrap();
+        world.add_bullet(Bullet::new(Pair { x: 0.0, y: 50.0 }, Pair { x: 1.0, y: 0.0 }));
+        world
+    }
+
+    #[test]
+    fn state_hashes_agree_until_worlds_diverge() {
+        let (mut a, mut b) = (hashed_world(), hashed_world());
+        assert_eq!(a.state_hash(), b.state_hash());
+        a.step();
+        assert_ne!(a.state_hash(), b.state_hash());
+        b.step();
+        assert_eq!(a.state_hash(), b.state_hash());
+        // Any of the hashed fields tells them apart
+        b.tanks.read().unwrap()[1].write().unwrap().aim = 1.0;
+        assert_ne!(a.state_hash(), b.state_hash());
+        let (a, b) = (hashed_world(), hashed_world());
+        b.bullets.read().unwrap()[0].write().unwrap().pos.y = 51.0;
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+}
@@ -1,10 +1,11 @@
 use std::cell::{UnsafeCell};
+use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::ops::{Add, Mul, Neg};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Pair {
     pub x: f32,
     pub y: f32,
@@ -19,10 +20,12 @@ impl Pair {
         Pair::both(0.0f32)
     }
 
+    // Heading of this vector, in [0, 2pi). Compare headings with in_arc, or by their wrapped
+    // difference, rather than directly: caller-supplied bounds needn't fall in the same range.
     pub fn ang(&self) -> f32 {
         let ang = self.y.atan2(self.x);
         if ang < 0.0 {
-            ang + ::std::f32::consts::PI
+            ang + 2.0 * ::std::f32::consts::PI
         } else {
             ang
         }
@@ -32,6 +35,14 @@ impl Pair {
         self.x + self.y
     }
 
+    pub fn dot(&self, other: &Pair) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn mag(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
     pub fn polar(head: f32) -> Pair {
         Pair {
             x: head.cos(),
@@ -52,6 +63,54 @@ impl Pair {
             y: self.y.max(other.y),
         }
     }
+
+    // Whether both components are within `eps` of `other`'s.
+    pub fn approx_eq(&self, other: &Pair, eps: f32) -> bool {
+        (self.x - other.x).abs() <= eps && (self.y - other.y).abs() <= eps
+    }
+
+    // The nearest point in `bound`, edges included. A box with no extent clamps everything onto
+    // its origin.
+    pub fn clamp_to(&self, bound: &AABB) -> Pair {
+        self.maxs(&bound.org).mins(&bound.opp())
+    }
+}
+
+// Bring an angle into [0, 2pi); None if it isn't finite.
+pub fn normalize_angle(a: f32) -> Option<f32> {
+    if !a.is_finite() {
+        return None;
+    }
+    let tau = 2.0 * ::std::f32::consts::PI;
+    let a = a.rem_euclid(tau);
+    // Tiny negative inputs round up to exactly tau
+    Some(if a >= tau { 0.0 } else { a })
+}
+
+// Whether heading `a` (as from Pair::ang) lies in the arc running counterclockwise from
+// `bounds.0` to `bounds.1`, which may be any radians with `bounds.0` <= `bounds.1`: an arc
+// crossing 0 wraps around, and one at least 2pi wide covers everything.
+pub fn in_arc(a: f32, bounds: (f32, f32)) -> bool {
+    let tau = 2.0 * ::std::f32::consts::PI;
+    let width = bounds.1 - bounds.0;
+    if width >= tau {
+        return true;
+    }
+    (a - bounds.0).rem_euclid(tau) < width
+}
+
+// Closest distance between two points moving linearly, from a0 to a1 and from b0 to b1, over the
+// same interval.
+pub fn closest_approach(a0: Pair, a1: Pair, b0: Pair, b1: Pair) -> f32 {
+    let d0 = a0 + (-b0);
+    let dv = (a1 + (-a0)) + (-(b1 + (-b0)));
+    let vv = dv.dot(&dv);
+    let t = if vv > 0.0 {
+        (-d0.dot(&dv) / vv).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+    (d0 + dv * t).mag()
 }
 
 impl Add for Pair {
@@ -97,7 +156,7 @@ impl Default for Pair {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AABB {
     pub org: Pair,
     pub dim: Pair,
@@ -141,15 +200,12 @@ impl AABB {
     }
 
     pub fn enclose(&self, point: Pair) -> AABB {
-        if !self.contains(point) {
-            let ur = self.org + self.dim;
-            AABB {
-                org: self.org.mins(&point),
-                dim: self.dim.maxs(&ur),
-            }
-        } else {
-            self.clone()
-        }
+        AABB::from_corners(self.org.mins(&point), self.opp().maxs(&point))
+    }
+
+    // In-place enclose, for building up a bound in a loop.
+    pub fn accumulate(&mut self, point: Pair) {
+        *self = self.enclose(point);
     }
 
     pub fn intersect(&self, other: &AABB) -> Option<AABB> {
@@ -166,7 +222,13 @@ impl AABB {
     pub fn over_points<I: Iterator<Item = Pair>>(mut it: I) -> AABB {
         match it.next() {
             None => AABB::empty(),
-            Some(pair) => it.fold(AABB::new(pair, Pair::zero()), |a, e| a.enclose(e)),
+            Some(pair) => {
+                let mut bound = AABB::new(pair, Pair::zero());
+                for p in it {
+                    bound.accumulate(p);
+                }
+                bound
+            }
         }
     }
 
@@ -174,6 +236,39 @@ impl AABB {
         let half = dim * 0.5;
         AABB::new(p + (-half), dim)
     }
+
+    // The shortest translation that moves a circle at `center` clear of this box, if they overlap.
+    pub fn push_out(&self, center: Pair, radius: f32) -> Option<Pair> {
+        let nearest = center.clamp_to(self);
+        let off = center + (-nearest);
+        let dist = off.mag();
+        if dist >= radius {
+            return None;
+        }
+        if dist > 0.0 {
+            return Some(off * ((radius - dist) / dist));
+        }
+        // The center itself is inside; leave through the closest face
+        let opp = self.opp();
+        let faces = [
+            (center.x - self.org.x, Pair { x: -1.0, y: 0.0 }),
+            (opp.x - center.x, Pair { x: 1.0, y: 0.0 }),
+            (center.y - self.org.y, Pair { x: 0.0, y: -1.0 }),
+            (opp.y - center.y, Pair { x: 0.0, y: 1.0 }),
+        ];
+        let (depth, dir) = faces
+            .iter()
+            .cloned()
+            .fold(faces[0], |best, face| if face.0 < best.0 { face } else { best });
+        Some(dir * (depth + radius))
+    }
+
+    pub fn grow(&self, margin: f32) -> AABB {
+        AABB::new(
+            self.org + (-Pair::both(margin)),
+            self.dim + Pair::both(2.0 * margin),
+        )
+    }
 }
 
 impl Default for AABB {
@@ -182,6 +277,12 @@ impl Default for AABB {
     }
 }
 
+impl FromIterator<Pair> for AABB {
+    fn from_iter<I: IntoIterator<Item = Pair>>(it: I) -> AABB {
+        AABB::over_points(it.into_iter())
+    }
+}
+
 pub trait SpaceQuery<'a, T: 'a> {
     type QueryIter: Iterator<Item = (Pair, &'a T)>;
     fn add_pt(&mut self, d: (Pair, T)) -> Result<(), T>;
@@ -210,6 +311,10 @@ pub struct QuadTreeBuilder<T> {
 
 const DEFAULT_QUAD_SIZE: usize = 4;
 
+// Points this close together count as the same spot. No amount of subdividing can separate them,
+// so a node full of them holds any number more instead.
+const COINCIDENT_EPS: f32 = 1e-6;
+
 impl<T> QuadTreeBuilder<T> {
     pub fn from_bound(bound: AABB) -> QuadTreeBuilder<T> {
         QuadTreeBuilder {
@@ -234,6 +339,19 @@ impl<T> QuadTreeBuilder<T> {
             max_data: self.max_data,
         }
     }
+
+    // Build a tree holding all of `points` at once, splitting top-down instead of as each point
+    // arrives; the tree comes out the same as adding them one by one. Points outside the bound
+    // are left out.
+    pub fn build_from(self, points: Vec<(Pair, T)>) -> QuadTreeNode<T> {
+        let mut root = self.build();
+        let points = points
+            .into_iter()
+            .filter(|d| root.bound.contains(d.0))
+            .collect();
+        root.insert_all(points);
+        root
+    }
 }
 
 impl<T> QuadTreeNode<T> {
@@ -291,6 +409,47 @@ impl<T> QuadTreeNode<T> {
 
         self.children = Some(Box::new(children));
     }
+
+    // Add points to an empty node. A node only splits once it holds more than `max_data`, so
+    // that's the only case that needs children.
+    fn insert_all(&mut self, points: Vec<(Pair, T)>) {
+        let coincident = points
+            .first()
+            .map_or(true, |first| points.iter().all(|d| d.0.approx_eq(&first.0, COINCIDENT_EPS)));
+        if points.len() <= self.max_data || coincident {
+            self.data = points;
+            return;
+        }
+        self.subdivide();
+        let children = self.children.as_mut().unwrap();
+        let mut parts: [Vec<(Pair, T)>; 4] = Default::default();
+        for datum in points {
+            match children.iter().position(|child| child.bound.contains(datum.0)) {
+                Some(idx) => parts[idx].push(datum),
+                None => panic!("Couldn't insert a point into any quadtree child! AABB: {:?} point: {:?}", self.bound, datum.0),
+            }
+        }
+        for (child, part) in children.iter_mut().zip(parts.iter_mut()) {
+            child.insert_all(core::mem::replace(part, Vec::new()));
+        }
+    }
+
+    // How many points lie in `b`; the same as query(b).count(), without building the results.
+    pub fn count(&self, b: AABB) -> usize {
+        self.count_in(&b)
+    }
+
+    fn count_in(&self, b: &AABB) -> usize {
+        let mut total = self.data.iter().filter(|d| b.contains(d.0)).count();
+        if let Some(children) = &self.children {
+            for child in children.iter() {
+                if b.intersect(&child.bound).is_some() {
+                    total += child.count_in(b);
+                }
+            }
+        }
+        total
+    }
 }
 
 pub struct QuadTreeChildrenIter<'a, T> {
@@ -394,6 +553,7 @@ impl<'a, T: 'a> Iterator for QuadTreeQueryIterator<'a, T> {
             }
 
             let top = self.stack.pop().unwrap();
+            self.index = 0;
 
             if let Some(children) = &top.children {
                 for child in children.into_iter() {
@@ -414,7 +574,8 @@ impl<'a, T: 'a> SpaceQuery<'a, T> for QuadTreeNode<T> {
             return Err(datum.1);
         }
 
-        if self.data.len() >= self.max_data {
+        let coincident = self.data.iter().all(|d| d.0.approx_eq(&pos, COINCIDENT_EPS));
+        if self.data.len() >= self.max_data && !coincident {
             self.subdivide();
         }
 
@@ -448,3 +609,194 @@ impl<'a, T: 'a> SpaceQuery<'a, T> for QuadTreeNode<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn ang_covers_the_full_circle() {
+        assert_eq!(Pair { x: 1.0, y: 0.0 }.ang(), 0.0);
+        assert!((Pair { x: 0.0, y: 1.0 }.ang() - PI / 2.0).abs() < 1e-6);
+        assert!((Pair { x: 0.0, y: -1.0 }.ang() - 1.5 * PI).abs() < 1e-6);
+        for i in 0..64 {
+            let a = Pair::polar(i as f32 * 0.1).ang();
+            assert!(a >= 0.0 && a < 2.0 * PI, "{} out of range", a);
+        }
+    }
+
+    #[test]
+    fn normalize_angle_rejects_non_finite() {
+        assert_eq!(normalize_angle(::std::f32::NAN), None);
+        assert_eq!(normalize_angle(::std::f32::INFINITY), None);
+        let a = normalize_angle(100.0 * PI).unwrap();
+        assert!(a >= 0.0 && a < 2.0 * PI);
+        assert!(a < 1e-3 || 2.0 * PI - a < 1e-3);
+        assert_eq!(normalize_angle(-1e-9), Some(0.0));
+    }
+
+    #[test]
+    fn in_arc_wraps() {
+        assert!(in_arc(0.5, (0.0, 1.0)));
+        assert!(!in_arc(1.0, (0.0, 1.0)));
+        // Crossing 0 from either side
+        assert!(in_arc(6.2, (-0.3, 0.3)));
+        assert!(in_arc(0.1, (6.0, 6.5)));
+        assert!(!in_arc(3.0, (-0.3, 0.3)));
+        assert!(in_arc(3.0, (-10.0, 10.0)));
+        assert!(!in_arc(1.0, (::std::f32::NAN, 1.0)));
+    }
+
+    #[test]
+    fn closest_approach_is_along_the_paths() {
+        let o = Pair::zero();
+        // Head on: they meet in the middle
+        let d = closest_approach(Pair { x: -10.0, y: 0.0 }, Pair { x: 10.0, y: 0.0 }, o, o);
+        assert!(d < 1e-6);
+        // Crossing paths, one a little behind the other
+        let d = closest_approach(
+            Pair { x: -5.0, y: 0.0 },
+            Pair { x: 5.0, y: 0.0 },
+            Pair { x: 0.0, y: -6.0 },
+            Pair { x: 0.0, y: 4.0 },
+        );
+        assert!((d - 2f32.sqrt() / 2.0).abs() < 1e-4, "{}", d);
+        // Neither moving is just the distance between them
+        assert_eq!(closest_approach(o, o, Pair { x: 3.0, y: 4.0 }, Pair { x: 3.0, y: 4.0 }), 5.0);
+    }
+
+    #[test]
+    fn bounds_enclose_every_point() {
+        let points = vec![
+            Pair { x: 3.0, y: -1.0 },
+            Pair { x: -2.0, y: 4.0 },
+            Pair { x: 0.0, y: 0.0 },
+        ];
+        let bound: AABB = points.iter().cloned().collect();
+        assert_eq!(bound, AABB::from_corners(Pair { x: -2.0, y: -1.0 }, Pair { x: 3.0, y: 4.0 }));
+        let mut streamed = AABB::new(points[0], Pair::zero());
+        for &p in &points[1..] {
+            streamed.accumulate(p);
+        }
+        assert_eq!(streamed, bound);
+        // Enclosing a point already inside changes nothing
+        assert_eq!(bound.enclose(Pair { x: 1.0, y: 1.0 }), bound);
+        let empty: AABB = Vec::new().into_iter().collect();
+        assert_eq!(empty, AABB::empty());
+    }
+
+    #[test]
+    fn clamp_to_keeps_points_in_bounds() {
+        let bound = AABB::from_corners(Pair { x: -10.0, y: 0.0 }, Pair { x: 10.0, y: 5.0 });
+        let inside = Pair { x: 3.0, y: 2.0 };
+        assert_eq!(inside.clamp_to(&bound), inside);
+        let edge = Pair { x: 10.0, y: 0.0 };
+        assert_eq!(edge.clamp_to(&bound), edge);
+        assert_eq!(Pair { x: 50.0, y: -3.0 }.clamp_to(&bound), Pair { x: 10.0, y: 0.0 });
+        assert_eq!(Pair { x: -11.0, y: 7.0 }.clamp_to(&bound), Pair { x: -10.0, y: 5.0 });
+        let point = AABB::new(Pair { x: 1.0, y: 1.0 }, Pair::zero());
+        assert_eq!(Pair { x: -4.0, y: 9.0 }.clamp_to(&point), Pair { x: 1.0, y: 1.0 });
+    }
+
+    #[test]
+    fn quadtree_queries_and_counts_find_every_point() {
+        let mut points = Vec::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                points.push((Pair { x: 5.0 + 10.0 * i as f32, y: 5.0 + 10.0 * j as f32 }, 10 * i + j));
+            }
+        }
+        let bound = AABB::new(Pair::zero(), Pair::both(100.0));
+        let tree = QuadTreeBuilder::from_bound(bound.clone())
+            .with_max_data(2)
+            .build_from(points.clone());
+        let boxes = vec![
+            bound,
+            AABB::from_corners(Pair { x: 12.0, y: 33.0 }, Pair { x: 71.0, y: 58.0 }),
+            AABB::from_corners(Pair { x: 45.0, y: 45.0 }, Pair { x: 55.0, y: 55.0 }),
+            AABB::from_corners(Pair { x: -50.0, y: 90.0 }, Pair { x: 30.0, y: 200.0 }),
+            AABB::from_corners(Pair { x: 6.0, y: 6.0 }, Pair { x: 14.0, y: 14.0 }),
+        ];
+        for b in boxes {
+            let mut expected: Vec<i32> = points.iter().filter(|p| b.contains(p.0)).map(|p| p.1).collect();
+            let mut found: Vec<i32> = tree.query(b.clone()).map(|(_, &v)| v).collect();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected, "in {:?}", b);
+            assert_eq!(tree.count(b), expected.len());
+        }
+    }
+
+    #[test]
+    fn push_out_clears_circles_from_boxes() {
+        let b = AABB::new(Pair::zero(), Pair::both(10.0));
+        assert_eq!(b.push_out(Pair { x: 13.0, y: 5.0 }, 2.0), None);
+        assert_eq!(b.push_out(Pair { x: 11.0, y: 5.0 }, 2.0), Some(Pair { x: 1.0, y: 0.0 }));
+        // From a corner, straight away from it
+        let center = Pair { x: 11.0, y: 11.0 };
+        let moved = center + b.push_out(center, 2.0).unwrap();
+        assert!(moved.approx_eq(&(Pair::both(10.0) + Pair::polar(PI / 4.0) * 2.0), 1e-5));
+        // A center inside leaves through the nearest face
+        assert_eq!(b.push_out(Pair { x: 2.0, y: 5.0 }, 2.0), Some(Pair { x: -4.0, y: 0.0 }));
+        assert_eq!(b.push_out(Pair { x: 5.0, y: 9.0 }, 2.0), Some(Pair { x: 0.0, y: 3.0 }));
+    }
+
+    // Every node of a tree, depth first, as its depth, corner and the values it holds.
+    fn shape(node: &QuadTreeNode<i32>, depth: usize, out: &mut Vec<(usize, Pair, Vec<i32>)>) {
+        out.push((depth, node.bound.org, node.data.iter().map(|d| d.1).collect()));
+        if let Some(ref children) = node.children {
+            for child in children.iter() {
+                shape(child, depth + 1, out);
+            }
+        }
+    }
+
+    #[test]
+    fn trees_built_at_once_match_trees_built_point_by_point() {
+        let mut points = vec![
+            (Pair { x: 1.0, y: 1.0 }, 0),
+            (Pair { x: 1.0, y: 1.0 }, 1),
+            (Pair { x: 1.0, y: 1.0 }, 2),
+            (Pair { x: 90.0, y: 10.0 }, 3),
+            (Pair { x: 150.0, y: 10.0 }, 4), // Out of bounds
+        ];
+        for i in 0..20 {
+            points.push((Pair { x: (i * 37 % 100) as f32, y: (i * 53 % 100) as f32 }, 10 + i));
+        }
+        let bound = AABB::new(Pair::zero(), Pair::both(100.0));
+        let all_at_once = QuadTreeBuilder::from_bound(bound.clone())
+            .with_max_data(2)
+            .build_from(points.clone());
+        let mut one_by_one = QuadTreeBuilder::from_bound(bound).with_max_data(2).build();
+        for p in points {
+            let id = p.1;
+            assert_eq!(one_by_one.add_pt(p).is_ok(), id != 4);
+        }
+        let (mut expected, mut found) = (Vec::new(), Vec::new());
+        shape(&one_by_one, 0, &mut expected);
+        shape(&all_at_once, 0, &mut found);
+        assert_eq!(found, expected);
+        assert!(expected.len() > 1);
+        assert!(!found.iter().any(|n| n.2.contains(&4)));
+    }
+
+    #[test]
+    fn coincident_points_share_a_node() {
+        let p = Pair { x: 3.0, y: 4.0 };
+        assert!(p.approx_eq(&Pair { x: 3.0, y: 4.0 + 1e-7 }, 1e-6));
+        assert!(!p.approx_eq(&Pair { x: 3.0 + 1e-3, y: 4.0 }, 1e-6));
+        // Far more points than a node holds, all in one place, neither split forever nor get lost
+        let bound = AABB::new(Pair::zero(), Pair::both(10.0));
+        let points: Vec<(Pair, usize)> = (0..50).map(|i| (p, i)).collect();
+        let mut one_by_one = QuadTreeBuilder::from_bound(bound.clone()).with_max_data(2).build();
+        for d in points.clone() {
+            assert!(one_by_one.add_pt(d).is_ok());
+        }
+        let all_at_once = QuadTreeBuilder::from_bound(bound.clone()).with_max_data(2).build_from(points);
+        for tree in &[one_by_one, all_at_once] {
+            assert!(tree.children.is_none());
+            assert_eq!(tree.query(bound.clone()).count(), 50);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+// A classic fixed-timestep accumulator: elapsed wall time is banked and spent in whole ticks, so
+// the simulation advances at a steady rate no matter how long each step takes to compute.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    pub tick: Duration,
+    pub max_ticks: usize, // Most ticks a single advance may ask for, to avoid the spiral of death
+    accumulator: Duration,
+}
+
+const DEFAULT_MAX_TICKS: usize = 10;
+
+impl FixedTimestep {
+    pub fn from_tps(tps: f64) -> FixedTimestep {
+        FixedTimestep {
+            tick: Duration::from_secs_f64(1.0 / tps),
+            max_ticks: DEFAULT_MAX_TICKS,
+            accumulator: Duration::from_secs(0),
+        }
+    }
+
+    pub fn with_max_ticks(self, max_ticks: usize) -> FixedTimestep {
+        FixedTimestep {
+            max_ticks: max_ticks,
+            ..self
+        }
+    }
+
+    // Bank `elapsed` and return how many ticks should be run to catch up.
+    pub fn advance(&mut self, elapsed: Duration) -> usize {
+        self.accumulator += elapsed;
+        let mut ticks = 0;
+        while self.accumulator >= self.tick && ticks < self.max_ticks {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        if self.accumulator >= self.tick {
+            // Hopelessly behind; drop the backlog instead of trying to catch up forever
+            self.accumulator = Duration::from_secs(0);
+        }
+        ticks
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::from_secs(0);
+    }
+
+    // Wall time left until the next tick is due.
+    pub fn until_next(&self) -> Duration {
+        self.tick
+            .checked_sub(self.accumulator)
+            .unwrap_or(Duration::from_secs(0))
+    }
+}
+
+// Pause and single-step control over a FixedTimestep, for when a viewer is driving the match.
+// Times are passed in rather than read, so this can be driven by hand.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    clock: FixedTimestep,
+    last: Instant,
+    paused: bool,
+    single_steps: usize, // Steps asked for while paused and not yet run
+}
+
+impl Playback {
+    pub fn new(clock: FixedTimestep, now: Instant) -> Playback {
+        Playback {
+            clock: clock,
+            last: now,
+            paused: false,
+            single_steps: 0,
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.single_steps = 0;
+    }
+
+    // Run one more step, and stay paused after it.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.single_steps += 1;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    // Nothing will run until something changes, so it's fine to block.
+    pub fn idle(&self) -> bool {
+        self.paused && self.single_steps == 0
+    }
+
+    // Call after blocking for any length of time; time spent waiting isn't caught up on.
+    pub fn woke(&mut self, now: Instant) {
+        self.last = now;
+        self.clock.reset();
+    }
+
+    // How many steps to run now.
+    pub fn ticks(&mut self, now: Instant) -> usize {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        if self.paused {
+            self.clock.reset();
+            if self.single_steps > 0 {
+                self.single_steps -= 1;
+                1
+            } else {
+                0
+            }
+        } else {
+            self.clock.advance(elapsed)
+        }
+    }
+
+    // How long to sleep before the next tick is due; None while paused.
+    pub fn until_next(&self) -> Option<Duration> {
+        if self.paused {
+            None
+        } else {
+            Some(self.clock.until_next())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn pause_then_step_runs_exactly_one_tick() {
+        let start = Instant::now();
+        let mut playback = Playback::new(FixedTimestep::from_tps(10.0), start);
+        playback.pause();
+        assert!(playback.idle());
+        assert_eq!(playback.ticks(start + ms(500)), 0);
+        playback.step();
+        assert!(!playback.idle());
+        assert_eq!(playback.ticks(start + ms(600)), 1);
+        assert!(playback.idle());
+        assert_eq!(playback.ticks(start + ms(700)), 0);
+        assert_eq!(playback.until_next(), None);
+    }
+
+    #[test]
+    fn steps_queue_up_while_paused() {
+        let start = Instant::now();
+        let mut playback = Playback::new(FixedTimestep::from_tps(10.0), start);
+        playback.step();
+        playback.step();
+        assert_eq!(playback.ticks(start), 1);
+        assert_eq!(playback.ticks(start), 1);
+        assert_eq!(playback.ticks(start), 0);
+    }
+
+    #[test]
+    fn waiting_is_not_caught_up_on() {
+        let start = Instant::now();
+        let mut playback = Playback::new(FixedTimestep::from_tps(10.0), start);
+        playback.pause();
+        // Blocked for a long time, then resumed
+        playback.woke(start + ms(5000));
+        playback.resume();
+        assert_eq!(playback.ticks(start + ms(5000)), 0);
+        assert_eq!(playback.ticks(start + ms(5100)), 1);
+    }
+
+    #[test]
+    fn accumulator_banks_partial_ticks() {
+        let mut clock = FixedTimestep::from_tps(10.0);
+        assert_eq!(clock.advance(ms(50)), 0);
+        assert_eq!(clock.until_next(), ms(50));
+        assert_eq!(clock.advance(ms(60)), 1);
+        assert_eq!(clock.advance(ms(290)), 3);
+        assert_eq!(clock.until_next(), ms(100));
+    }
+
+    #[test]
+    fn accumulator_drops_hopeless_backlog() {
+        let mut clock = FixedTimestep::from_tps(10.0).with_max_ticks(3);
+        assert_eq!(clock.advance(ms(10_000)), 3);
+        assert_eq!(clock.until_next(), ms(100));
+        assert_eq!(clock.advance(ms(100)), 1);
+    }
+}
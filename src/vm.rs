@@ -3,14 +3,17 @@ use std::sync::{Arc, Mutex};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
+use serde::{Deserialize, Serialize};
+
 use wasmi::{
     nan_preserving_float::{F32, F64},
     ExternVal, Externals, FuncInstance, FuncInvocation, FuncRef, HostError, ImportsBuilder,
     ModuleImportResolver, ModuleInstance, ResumableError, RuntimeArgs, RuntimeValue, Signature,
-    Trap, TrapKind, ValueType, MemoryRef,
+    Trap, TrapKind, ValueType, MemoryRef, ModuleRef,
 };
 
-use sim::Configuration;
+use rng::Rng;
+use sim::{BulletKind, Configuration};
 
 #[derive(Clone, Copy, Debug)]
 enum HostCall {
@@ -22,6 +25,19 @@ enum HostCall {
     BinaryOpF64(BinaryOp),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostCallError {
+    UnknownName(String),
+}
+
+impl core::fmt::Display for HostCallError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HostCallError::UnknownName(name) => write!(f, "Export {} not found", name),
+        }
+    }
+}
+
 const B0: usize = 0;
 const B1: usize = NUM_UPCALLS;
 const B2: usize = NUM_UPCALLS + NUM_CONSTANTS;
@@ -31,16 +47,30 @@ const B5: usize = NUM_UPCALLS + NUM_CONSTANTS + 2 * NUM_UNOPS + NUM_BINOPS;
 const B6: usize = NUM_UPCALLS + NUM_CONSTANTS + 2 * NUM_UNOPS + 2 * NUM_BINOPS;
 
 impl HostCall {
-    pub fn from_name(name: &str) -> Result<Self, ()> {
+    pub fn from_name(name: &str) -> Result<Self, HostCallError> {
         match name {
             "scan" => Ok(HostCall::Upcall(UpcallId::Scan)),
+            "scan_into" => Ok(HostCall::Upcall(UpcallId::ScanInto)),
+            "scan_bullets" => Ok(HostCall::Upcall(UpcallId::ScanBullets)),
+            "scan_hottest" => Ok(HostCall::Upcall(UpcallId::ScanHottest)),
             "fire" => Ok(HostCall::Upcall(UpcallId::Fire)),
+            "fire_kind" => Ok(HostCall::Upcall(UpcallId::FireKind)),
             "aim" => Ok(HostCall::Upcall(UpcallId::Aim)),
             "turn" => Ok(HostCall::Upcall(UpcallId::Turn)),
             "gpsx" => Ok(HostCall::Upcall(UpcallId::GPSX)),
             "gpsy" => Ok(HostCall::Upcall(UpcallId::GPSY)),
             "temp" => Ok(HostCall::Upcall(UpcallId::Temp)),
+            "cooldown_remaining" => Ok(HostCall::Upcall(UpcallId::CooldownRemaining)),
+            "was_scanned" => Ok(HostCall::Upcall(UpcallId::WasScanned)),
+            "my_team" => Ok(HostCall::Upcall(UpcallId::MyTeam)),
+            "my_id" => Ok(HostCall::Upcall(UpcallId::MyId)),
+            "team_centroid_x" => Ok(HostCall::Upcall(UpcallId::TeamCentroidX)),
+            "team_centroid_y" => Ok(HostCall::Upcall(UpcallId::TeamCentroidY)),
+            "bearing_to_team" => Ok(HostCall::Upcall(UpcallId::BearingToTeam)),
+            "dist_to_team" => Ok(HostCall::Upcall(UpcallId::DistToTeam)),
             "forward" => Ok(HostCall::Upcall(UpcallId::Forward)),
+            "set_throttle" => Ok(HostCall::Upcall(UpcallId::SetThrottle)),
+            "set_label" => Ok(HostCall::Upcall(UpcallId::SetLabel)),
             "explode" => Ok(HostCall::Upcall(UpcallId::Explode)),
             "post_string" => Ok(HostCall::Upcall(UpcallId::PostString)),
             "post_int32" => Ok(HostCall::Upcall(UpcallId::PostI32)),
@@ -60,6 +90,11 @@ impl HostCall {
             "TANK_HIT_RADIUS" => Ok(HostCall::Constant(ConstantId::TankHitRadius)),
             "TANK_VELOCITY" => Ok(HostCall::Constant(ConstantId::TankVelocity)),
             "EXPLOSION_RADIUS" => Ok(HostCall::Constant(ConstantId::ExplosionRadius)),
+            "BULLET_RADIUS" => Ok(HostCall::Constant(ConstantId::BulletRadius)),
+            "ARENA_MIN_X" => Ok(HostCall::Constant(ConstantId::ArenaMinX)),
+            "ARENA_MIN_Y" => Ok(HostCall::Constant(ConstantId::ArenaMinY)),
+            "ARENA_MAX_X" => Ok(HostCall::Constant(ConstantId::ArenaMaxX)),
+            "ARENA_MAX_Y" => Ok(HostCall::Constant(ConstantId::ArenaMaxY)),
             "abs_float" => Ok(HostCall::UnaryOpF32(UnaryOp::Abs)),
             "acos_float" => Ok(HostCall::UnaryOpF32(UnaryOp::Acos)),
             "acosh_float" => Ok(HostCall::UnaryOpF32(UnaryOp::Acosh)),
@@ -136,7 +171,7 @@ impl HostCall {
             "min_double" => Ok(HostCall::BinaryOpF64(BinaryOp::Min)),
             "powf_double" => Ok(HostCall::BinaryOpF64(BinaryOp::Powf)),
             "rem_euclid_double" => Ok(HostCall::BinaryOpF64(BinaryOp::RemEuclid)),
-            _ => Err(()),
+            _ => Err(HostCallError::UnknownName(name.into())),
         }
     }
 
@@ -145,14 +180,34 @@ impl HostCall {
             HostCall::Upcall(UpcallId::Scan) => {
                 (vec![ValueType::F32, ValueType::F32], Some(ValueType::I64))
             }
-            HostCall::Upcall(UpcallId::Fire) => (vec![], None),
+            HostCall::Upcall(UpcallId::ScanInto) => {
+                (vec![ValueType::F32, ValueType::F32, ValueType::I32], None)
+            }
+            HostCall::Upcall(UpcallId::ScanBullets) => {
+                (vec![ValueType::F32, ValueType::F32], Some(ValueType::I32))
+            }
+            HostCall::Upcall(UpcallId::ScanHottest) => {
+                (vec![ValueType::F32, ValueType::F32], Some(ValueType::F32))
+            }
+            HostCall::Upcall(UpcallId::Fire) => (vec![], Some(ValueType::I32)),
+            HostCall::Upcall(UpcallId::FireKind) => (vec![ValueType::I32], Some(ValueType::I32)),
             HostCall::Upcall(UpcallId::Aim) => (vec![ValueType::F32], None),
             HostCall::Upcall(UpcallId::Turn) => (vec![ValueType::F32], None),
             HostCall::Upcall(UpcallId::GPSX) => (vec![], Some(ValueType::F32)),
             HostCall::Upcall(UpcallId::GPSY) => (vec![], Some(ValueType::F32)),
             HostCall::Upcall(UpcallId::Temp) => (vec![], Some(ValueType::I32)),
+            HostCall::Upcall(UpcallId::CooldownRemaining) => (vec![], Some(ValueType::I32)),
+            HostCall::Upcall(UpcallId::WasScanned) => (vec![], Some(ValueType::I32)),
+            HostCall::Upcall(UpcallId::MyTeam) => (vec![], Some(ValueType::I32)),
+            HostCall::Upcall(UpcallId::MyId) => (vec![], Some(ValueType::I64)),
+            HostCall::Upcall(UpcallId::TeamCentroidX) => (vec![], Some(ValueType::F32)),
+            HostCall::Upcall(UpcallId::TeamCentroidY) => (vec![], Some(ValueType::F32)),
+            HostCall::Upcall(UpcallId::BearingToTeam) => (vec![ValueType::I32], Some(ValueType::F32)),
+            HostCall::Upcall(UpcallId::DistToTeam) => (vec![ValueType::I32], Some(ValueType::F32)),
             HostCall::Upcall(UpcallId::Forward) => (vec![], None),
+            HostCall::Upcall(UpcallId::SetThrottle) => (vec![ValueType::F32], None),
             HostCall::Upcall(UpcallId::Explode) => (vec![], None),
+            HostCall::Upcall(UpcallId::SetLabel) => (vec![ValueType::I32, ValueType::I32], None),
             HostCall::Upcall(UpcallId::PostString) => (vec![ValueType::I32], None),
             HostCall::Upcall(UpcallId::PostI32) => (vec![ValueType::I32], None),
             HostCall::Upcall(UpcallId::PostI64) => (vec![ValueType::I32], None),
@@ -175,20 +230,12 @@ impl HostCall {
 
     pub fn from_id(id: usize) -> Option<Self> {
         match id {
-            x @ B0..B1 => Some(HostCall::Upcall(FromPrimitive::from_usize(x - B0).unwrap())),
-            x @ B1..B2 => Some(HostCall::Constant(FromPrimitive::from_usize(x - B1).unwrap())),
-            x @ B2..B3 => Some(HostCall::UnaryOpF32(
-                FromPrimitive::from_usize(x - B2).unwrap(),
-            )),
-            x @ B3..B4 => Some(HostCall::BinaryOpF32(
-                FromPrimitive::from_usize(x - B3).unwrap(),
-            )),
-            x @ B4..B5 => Some(HostCall::UnaryOpF64(
-                FromPrimitive::from_usize(x - B4).unwrap(),
-            )),
-            x @ B5..B6 => Some(HostCall::BinaryOpF64(
-                FromPrimitive::from_usize(x - B5).unwrap(),
-            )),
+            x @ B0..B1 => FromPrimitive::from_usize(x - B0).map(HostCall::Upcall),
+            x @ B1..B2 => FromPrimitive::from_usize(x - B1).map(HostCall::Constant),
+            x @ B2..B3 => FromPrimitive::from_usize(x - B2).map(HostCall::UnaryOpF32),
+            x @ B3..B4 => FromPrimitive::from_usize(x - B3).map(HostCall::BinaryOpF32),
+            x @ B4..B5 => FromPrimitive::from_usize(x - B4).map(HostCall::UnaryOpF64),
+            x @ B5..B6 => FromPrimitive::from_usize(x - B5).map(HostCall::BinaryOpF64),
             _ => None,
         }
     }
@@ -209,13 +256,27 @@ impl HostCall {
 #[derive(Clone, Copy, Debug, FromPrimitive)]
 enum UpcallId {
     Scan,
+    ScanInto,
+    ScanBullets,
+    ScanHottest,
     Fire,
+    FireKind,
     Aim,
     Turn,
     GPSX,
     GPSY,
     Temp,
+    CooldownRemaining,
+    WasScanned,
+    MyTeam,
+    MyId,
+    TeamCentroidX,
+    TeamCentroidY,
+    BearingToTeam,
+    DistToTeam,
     Forward,
+    SetThrottle,
+    SetLabel,
     Explode,
     PostString,
     PostI32,
@@ -241,7 +302,12 @@ enum ConstantId {
     BulletSpacing,
     TankHitRadius,
     TankVelocity,
-    ExplosionRadius, // Must be last, or else change the constant below
+    ExplosionRadius,
+    BulletRadius,
+    ArenaMinX,
+    ArenaMinY,
+    ArenaMaxX,
+    ArenaMaxY, // Must be last, or else change the constant below
 }
 
 impl ConstantId {
@@ -257,26 +323,37 @@ impl ConstantId {
             ConstantId::TankHitRadius => ValueType::F32,
             ConstantId::TankVelocity => ValueType::F32,
             ConstantId::ExplosionRadius => ValueType::F32,
+            ConstantId::BulletRadius => ValueType::F32,
+            ConstantId::ArenaMinX => ValueType::F32,
+            ConstantId::ArenaMinY => ValueType::F32,
+            ConstantId::ArenaMaxX => ValueType::F32,
+            ConstantId::ArenaMaxY => ValueType::F32,
         }
     }
 
     pub fn runtime_value(&self, config: &Configuration) -> RuntimeValue {
         match &self {
-            ConstantId::ShootHeat => RuntimeValue::I32(config.shoot_heat),
+            // Of a standard shot; other kinds differ by their speed
+            ConstantId::ShootHeat => RuntimeValue::I32(config.shot_heat(BulletKind::Standard)),
             ConstantId::IdleHeat => RuntimeValue::I32(config.idle_heat),
             ConstantId::MoveHeat => RuntimeValue::I32(config.move_heat),
             ConstantId::DeathHeat => RuntimeValue::I32(config.death_heat),
             ConstantId::InstrsPerStep => RuntimeValue::I64(i64::from_ne_bytes(config.instrs_per_step.to_ne_bytes())),
             ConstantId::BulletVelocity => RuntimeValue::F32(F32::from_float(config.bullet_v)),
             ConstantId::BulletSpacing => RuntimeValue::F32(F32::from_float(config.bullet_s)),
-            ConstantId::TankHitRadius => RuntimeValue::F32(F32::from_float(config.hit_rad)),
+            ConstantId::TankHitRadius => RuntimeValue::F32(F32::from_float(config.tank_radius)),
             ConstantId::TankVelocity => RuntimeValue::F32(F32::from_float(config.tank_v)),
             ConstantId::ExplosionRadius => RuntimeValue::F32(F32::from_float(config.explode_rad)),
+            ConstantId::BulletRadius => RuntimeValue::F32(F32::from_float(config.bullet_radius)),
+            ConstantId::ArenaMinX => RuntimeValue::F32(F32::from_float(config.world_bound.org.x)),
+            ConstantId::ArenaMinY => RuntimeValue::F32(F32::from_float(config.world_bound.org.y)),
+            ConstantId::ArenaMaxX => RuntimeValue::F32(F32::from_float(config.world_bound.opp().x)),
+            ConstantId::ArenaMaxY => RuntimeValue::F32(F32::from_float(config.world_bound.opp().y)),
         }
     }
 }
 
-const NUM_CONSTANTS: usize = ConstantId::ExplosionRadius as usize + 1;
+const NUM_CONSTANTS: usize = ConstantId::ArenaMaxY as usize + 1;
 
 #[repr(usize)]
 #[derive(Clone, Copy, Debug, FromPrimitive)]
@@ -359,11 +436,12 @@ impl UnaryOp {
         unary_body!(self, x)
     }
 
-    pub fn do_runtime(&self, x: RuntimeValue) -> RuntimeValue {
+    // None for anything but a float.
+    pub fn do_runtime(&self, x: RuntimeValue) -> Option<RuntimeValue> {
         match x {
-            RuntimeValue::F32(x) => RuntimeValue::F32(F32::from_float(self.do_f32(x.to_float()))),
-            RuntimeValue::F64(x) => RuntimeValue::F64(F64::from_float(self.do_f64(x.to_float()))),
-            _ => panic!("Attempt to do floating point operations on non-floating point types"),
+            RuntimeValue::F32(x) => Some(RuntimeValue::F32(F32::from_float(self.do_f32(x.to_float())))),
+            RuntimeValue::F64(x) => Some(RuntimeValue::F64(F64::from_float(self.do_f64(x.to_float())))),
+            _ => None,
         }
     }
 }
@@ -409,26 +487,66 @@ impl BinaryOp {
         binary_body!(self, a, b)
     }
 
-    pub fn do_runtime(&self, a: RuntimeValue, b: RuntimeValue) -> RuntimeValue {
+    // None unless both are floats of the same width.
+    pub fn do_runtime(&self, a: RuntimeValue, b: RuntimeValue) -> Option<RuntimeValue> {
         match (a, b) {
-            (RuntimeValue::F32(a), RuntimeValue::F32(b)) => RuntimeValue::F32(F32::from_float(self.do_f32(a.to_float(), b.to_float()))),
-            (RuntimeValue::F64(a), RuntimeValue::F64(b)) => RuntimeValue::F64(F64::from_float(self.do_f64(a.to_float(), b.to_float()))),
-            _ => panic!("Attempt to do floating point operations on non-floating point or difform floating point types"),
+            (RuntimeValue::F32(a), RuntimeValue::F32(b)) => Some(RuntimeValue::F32(F32::from_float(self.do_f32(a.to_float(), b.to_float())))),
+            (RuntimeValue::F64(a), RuntimeValue::F64(b)) => Some(RuntimeValue::F64(F64::from_float(self.do_f64(a.to_float(), b.to_float())))),
+            _ => None,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+// Serde helpers for the return slots in Upcall: a slot is written as whatever value it holds so
+// far, and read back as a fresh slot holding that value.
+mod slot {
+    use std::sync::{Arc, Mutex};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(v: &Arc<Mutex<Option<T>>>, s: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        v.lock().unwrap().serialize(s)
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Arc<Mutex<Option<T>>>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(d).map(|v| Arc::new(Mutex::new(v)))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Upcall {
     None,
-    Scan(f32, f32, Arc<Mutex<Option<u64>>>),
-    Fire,
+    Scan(f32, f32, #[serde(with = "slot")] Arc<Mutex<Option<u64>>>),
+    ScanInto(f32, f32, u32), // Writes what Scan would return to memory at the pointer
+    ScanBullets(f32, f32, #[serde(with = "slot")] Arc<Mutex<Option<i32>>>),
+    ScanHottest(f32, f32, #[serde(with = "slot")] Arc<Mutex<Option<f32>>>), // Bearing, or -1
+    Fire(#[serde(with = "slot")] Arc<Mutex<Option<i32>>>), // Gets a FireStatus
+    FireKind(i32, #[serde(with = "slot")] Arc<Mutex<Option<i32>>>), // Fire, with a BulletKind
     Aim(f32),
     Turn(f32),
-    GPSX(Arc<Mutex<Option<f32>>>),
-    GPSY(Arc<Mutex<Option<f32>>>),
-    Temp(Arc<Mutex<Option<i32>>>),
+    GPSX(#[serde(with = "slot")] Arc<Mutex<Option<f32>>>),
+    GPSY(#[serde(with = "slot")] Arc<Mutex<Option<f32>>>),
+    Temp(#[serde(with = "slot")] Arc<Mutex<Option<i32>>>),
+    CooldownRemaining(#[serde(with = "slot")] Arc<Mutex<Option<i32>>>),
+    WasScanned(#[serde(with = "slot")] Arc<Mutex<Option<i32>>>), // Enemy scans caught us last step
+    MyTeam(#[serde(with = "slot")] Arc<Mutex<Option<i32>>>),
+    MyId(#[serde(with = "slot")] Arc<Mutex<Option<i64>>>),
+    TeamCentroidX(#[serde(with = "slot")] Arc<Mutex<Option<f32>>>), // NaN if the team is all dead
+    TeamCentroidY(#[serde(with = "slot")] Arc<Mutex<Option<f32>>>),
+    // To the nearest living tank on the given team; -1 if there is none
+    BearingToTeam(i32, #[serde(with = "slot")] Arc<Mutex<Option<f32>>>),
+    DistToTeam(i32, #[serde(with = "slot")] Arc<Mutex<Option<f32>>>),
     Forward,
+    SetThrottle(f32),
+    SetLabel(String),
     PostString(String),
     PostI32(i32),
     PostU32(u32),
@@ -444,13 +562,27 @@ impl Upcall {
         match self {
             Upcall::None => false,
             Upcall::Scan(_, _, _) => false,
-            Upcall::Fire => true,
+            Upcall::ScanInto(_, _, _) => false,
+            Upcall::ScanBullets(_, _, _) => false,
+            Upcall::ScanHottest(_, _, _) => false,
+            Upcall::Fire(_) => true,
+            Upcall::FireKind(_, _) => true,
             Upcall::Aim(_) => false,
             Upcall::Turn(_) => false,
             Upcall::GPSX(_) => false,
             Upcall::GPSY(_) => false,
             Upcall::Temp(_) => false,
+            Upcall::CooldownRemaining(_) => false,
+            Upcall::WasScanned(_) => false,
+            Upcall::MyTeam(_) => false,
+            Upcall::MyId(_) => false,
+            Upcall::TeamCentroidX(_) => false,
+            Upcall::TeamCentroidY(_) => false,
+            Upcall::BearingToTeam(_, _) => false,
+            Upcall::DistToTeam(_, _) => false,
             Upcall::Forward => true,
+            Upcall::SetThrottle(_) => false,
+            Upcall::SetLabel(_) => false,
             Upcall::PostString(_) => false,
             Upcall::PostI32(_) => false,
             Upcall::PostU32(_) => false,
@@ -463,18 +595,92 @@ impl Upcall {
     }
 }
 
+impl Upcall {
+    // Copy the return value out of `other`, a resolved upcall of the same kind, into this one.
+    pub fn fill_from(&self, other: &Upcall) {
+        match (self, other) {
+            (Upcall::Scan(_, _, a), Upcall::Scan(_, _, b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::ScanBullets(_, _, a), Upcall::ScanBullets(_, _, b)) => {
+                *a.lock().unwrap() = *b.lock().unwrap()
+            }
+            (Upcall::ScanHottest(_, _, a), Upcall::ScanHottest(_, _, b)) => {
+                *a.lock().unwrap() = *b.lock().unwrap()
+            }
+            (Upcall::Fire(a), Upcall::Fire(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::FireKind(_, a), Upcall::FireKind(_, b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::GPSX(a), Upcall::GPSX(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::GPSY(a), Upcall::GPSY(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::Temp(a), Upcall::Temp(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::CooldownRemaining(a), Upcall::CooldownRemaining(b)) => {
+                *a.lock().unwrap() = *b.lock().unwrap()
+            }
+            (Upcall::WasScanned(a), Upcall::WasScanned(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::MyTeam(a), Upcall::MyTeam(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::MyId(a), Upcall::MyId(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::TeamCentroidX(a), Upcall::TeamCentroidX(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::TeamCentroidY(a), Upcall::TeamCentroidY(b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::BearingToTeam(_, a), Upcall::BearingToTeam(_, b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            (Upcall::DistToTeam(_, a), Upcall::DistToTeam(_, b)) => *a.lock().unwrap() = *b.lock().unwrap(),
+            _ => (),
+        }
+    }
+
+    // Answer with any bits at all, NaNs and infinities included, for fuzzing. One time in
+    // sixteen the call is left unanswered instead, as a buggy host might.
+    pub fn fill_random(&self, rng: &mut Rng) {
+        let bits = rng.next_u64();
+        if bits & 0xf == 0 {
+            return;
+        }
+        let (i32_bits, f32_bits) = (bits as u32 as i32, f32::from_bits((bits >> 32) as u32));
+        match self {
+            Upcall::Scan(_, _, rv) => *rv.lock().unwrap() = Some(bits),
+            Upcall::MyId(rv) => *rv.lock().unwrap() = Some(bits as i64),
+            Upcall::ScanBullets(_, _, rv)
+            | Upcall::Fire(rv)
+            | Upcall::FireKind(_, rv)
+            | Upcall::Temp(rv)
+            | Upcall::CooldownRemaining(rv)
+            | Upcall::WasScanned(rv)
+            | Upcall::MyTeam(rv) => *rv.lock().unwrap() = Some(i32_bits),
+            Upcall::ScanHottest(_, _, rv)
+            | Upcall::GPSX(rv)
+            | Upcall::GPSY(rv)
+            | Upcall::TeamCentroidX(rv)
+            | Upcall::TeamCentroidY(rv)
+            | Upcall::BearingToTeam(_, rv)
+            | Upcall::DistToTeam(_, rv) => *rv.lock().unwrap() = Some(f32_bits),
+            _ => (),
+        }
+    }
+}
+
 impl core::fmt::Display for Upcall {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Upcall::None => write!(f, "none")?,
             Upcall::Scan(a, b, _) => write!(f, "scan between {} and {}", a, b)?,
-            Upcall::Fire => write!(f, "fire")?,
+            Upcall::ScanInto(a, b, p) => write!(f, "scan between {} and {} into {:#x}", a, b, p)?,
+            Upcall::ScanBullets(a, b, _) => write!(f, "scan for bullets between {} and {}", a, b)?,
+            Upcall::ScanHottest(a, b, _) => write!(f, "scan for the hottest enemy between {} and {}", a, b)?,
+            Upcall::Fire(_) => write!(f, "fire")?,
+            Upcall::FireKind(k, _) => write!(f, "fire bullet kind {}", k)?,
             Upcall::Aim(h) => write!(f, "aim at {}", h)?,
             Upcall::Turn(h) => write!(f, "turn to {}", h)?,
             Upcall::GPSX(_) => write!(f, "get GPS X")?,
             Upcall::GPSY(_) => write!(f, "get GPS Y")?,
             Upcall::Temp(_) => write!(f, "get temperature")?,
+            Upcall::CooldownRemaining(_) => write!(f, "get cooldown")?,
+            Upcall::WasScanned(_) => write!(f, "check whether scanned")?,
+            Upcall::MyTeam(_) => write!(f, "get own team")?,
+            Upcall::MyId(_) => write!(f, "get own id")?,
+            Upcall::TeamCentroidX(_) => write!(f, "get team centroid X")?,
+            Upcall::TeamCentroidY(_) => write!(f, "get team centroid Y")?,
+            Upcall::BearingToTeam(t, _) => write!(f, "get bearing to team {}", t)?,
+            Upcall::DistToTeam(t, _) => write!(f, "get distance to team {}", t)?,
             Upcall::Forward => write!(f, "move forward")?,
+            Upcall::SetThrottle(t) => write!(f, "set throttle to {}", t)?,
+            Upcall::SetLabel(s) => write!(f, "set label to {:?}", s)?,
             Upcall::PostString(s) => write!(f, "post {:?}", s)?,
             Upcall::PostI32(s) => write!(f, "post {:?}", s)?,
             Upcall::PostU32(s) => write!(f, "post {:?}", s)?,
@@ -501,7 +707,7 @@ impl ModuleImportResolver for HostImports {
         signature: &Signature,
     ) -> Result<FuncRef, wasmi::Error> {
         let id = HostCall::from_name(field_name)
-            .map_err(|_| wasmi::Error::Instantiation(format!("Export {} not found", field_name)))?;
+            .map_err(|e| wasmi::Error::Instantiation(e.to_string()))?;
         let (params, rt) = id.signature();
         if params != signature.params() || rt != signature.return_type() {
             return Err(wasmi::Error::Instantiation(format!(
@@ -516,10 +722,56 @@ impl ModuleImportResolver for HostImports {
     }
 }
 
+// How many times host calls of each kind were made; see VM::set_call_stats.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallStats {
+    pub upcalls: u64,
+    pub constants: u64,
+    pub unary_f32: u64,
+    pub binary_f32: u64,
+    pub unary_f64: u64,
+    pub binary_f64: u64,
+}
+
+impl CallStats {
+    fn count(&mut self, call: &HostCall) {
+        let counter = match call {
+            HostCall::Upcall(_) => &mut self.upcalls,
+            HostCall::Constant(_) => &mut self.constants,
+            HostCall::UnaryOpF32(_) => &mut self.unary_f32,
+            HostCall::BinaryOpF32(_) => &mut self.binary_f32,
+            HostCall::UnaryOpF64(_) => &mut self.unary_f64,
+            HostCall::BinaryOpF64(_) => &mut self.binary_f64,
+        };
+        *counter += 1;
+    }
+
+    // Math calls, as opposed to upcalls and constants.
+    pub fn math(&self) -> u64 {
+        self.unary_f32 + self.binary_f32 + self.unary_f64 + self.binary_f64
+    }
+}
+
 #[derive(Clone, Debug)]
 struct HostFuncs {
     memory: MemoryRef,
-    config: Configuration,
+    config: Arc<Configuration>,
+    stats: Option<Arc<Mutex<CallStats>>>,
+}
+
+impl HostFuncs {
+    // Out-of-bounds accesses trap, just as they would from inside the module.
+    fn read_bytes(&self, ptr: u32, len: usize) -> Result<Vec<u8>, Trap> {
+        self.memory
+            .get(ptr, len)
+            .map_err(|_| Trap::new(TrapKind::MemoryAccessOutOfBounds))
+    }
+
+    fn write_bytes(&self, ptr: u32, data: &[u8]) -> Result<(), Trap> {
+        self.memory
+            .set(ptr, data)
+            .map_err(|_| Trap::new(TrapKind::MemoryAccessOutOfBounds))
+    }
 }
 
 impl Externals for HostFuncs {
@@ -529,6 +781,9 @@ impl Externals for HostFuncs {
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap> {
         let call = HostCall::from_id(index).ok_or(Trap::new(TrapKind::TableAccessOutOfBounds))?;
+        if let Some(stats) = &self.stats {
+            stats.lock().unwrap().count(&call);
+        }
         //println!("call: {:?}", call);
         match call {
             HostCall::Upcall(uc) => Err(Trap::new(TrapKind::Host(Box::new(match uc {
@@ -537,14 +792,69 @@ impl Externals for HostFuncs {
                     args.nth_checked::<F32>(1)?.to_float(),
                     Arc::new(Mutex::new(None)),
                 ),
-                UpcallId::Fire => Upcall::Fire,
+                UpcallId::ScanInto => {
+                    let ptr = u32::from_ne_bytes(args.nth_checked::<i32>(2)?.to_ne_bytes());
+                    // Check now, so the result can always be written later
+                    match self.read_bytes(ptr, 8) {
+                        Ok(_) => Upcall::ScanInto(
+                            args.nth_checked::<F32>(0)?.to_float(),
+                            args.nth_checked::<F32>(1)?.to_float(),
+                            ptr,
+                        ),
+                        Err(_) => Upcall::Explode,
+                    }
+                }
+                UpcallId::ScanBullets => Upcall::ScanBullets(
+                    args.nth_checked::<F32>(0)?.to_float(),
+                    args.nth_checked::<F32>(1)?.to_float(),
+                    Arc::new(Mutex::new(None)),
+                ),
+                UpcallId::ScanHottest => Upcall::ScanHottest(
+                    args.nth_checked::<F32>(0)?.to_float(),
+                    args.nth_checked::<F32>(1)?.to_float(),
+                    Arc::new(Mutex::new(None)),
+                ),
+                UpcallId::Fire => Upcall::Fire(Arc::new(Mutex::new(None))),
+                UpcallId::FireKind => Upcall::FireKind(args.nth_checked::<i32>(0)?, Arc::new(Mutex::new(None))),
                 UpcallId::Aim => Upcall::Aim(args.nth_checked::<F32>(0)?.to_float()),
                 UpcallId::Turn => Upcall::Turn(args.nth_checked::<F32>(0)?.to_float()),
                 UpcallId::GPSX => Upcall::GPSX(Arc::new(Mutex::new(None))),
                 UpcallId::GPSY => Upcall::GPSY(Arc::new(Mutex::new(None))),
                 UpcallId::Temp => Upcall::Temp(Arc::new(Mutex::new(None))),
+                UpcallId::CooldownRemaining => Upcall::CooldownRemaining(Arc::new(Mutex::new(None))),
+                UpcallId::WasScanned => Upcall::WasScanned(Arc::new(Mutex::new(None))),
+                UpcallId::MyTeam => Upcall::MyTeam(Arc::new(Mutex::new(None))),
+                UpcallId::MyId => Upcall::MyId(Arc::new(Mutex::new(None))),
+                UpcallId::TeamCentroidX => Upcall::TeamCentroidX(Arc::new(Mutex::new(None))),
+                UpcallId::TeamCentroidY => Upcall::TeamCentroidY(Arc::new(Mutex::new(None))),
+                UpcallId::BearingToTeam => {
+                    Upcall::BearingToTeam(args.nth_checked::<i32>(0)?, Arc::new(Mutex::new(None)))
+                }
+                UpcallId::DistToTeam => {
+                    Upcall::DistToTeam(args.nth_checked::<i32>(0)?, Arc::new(Mutex::new(None)))
+                }
                 UpcallId::Forward => Upcall::Forward,
+                UpcallId::SetThrottle => Upcall::SetThrottle(args.nth_checked::<F32>(0)?.to_float()),
                 UpcallId::Explode => Upcall::Explode,
+                UpcallId::SetLabel => {
+                    // Anything past `max_label_len` bytes is cut off, along with any character
+                    // that cut splits
+                    let ptr = u32::from_ne_bytes(args.nth_checked::<i32>(0)?.to_ne_bytes());
+                    let len = u32::from_ne_bytes(args.nth_checked::<i32>(1)?.to_ne_bytes()) as usize;
+                    match self.read_bytes(ptr, len.min(self.config.max_label_len)) {
+                        Ok(v) => match String::from_utf8(v) {
+                            Ok(s) => Upcall::SetLabel(s),
+                            Err(e) if e.utf8_error().error_len().is_none() => {
+                                let valid = e.utf8_error().valid_up_to();
+                                let mut v = e.into_bytes();
+                                v.truncate(valid);
+                                Upcall::SetLabel(String::from_utf8_lossy(&v).into_owned())
+                            }
+                            Err(_) => Upcall::Explode,
+                        },
+                        Err(_) => Upcall::Explode,
+                    }
+                }
                 UpcallId::PostString => {
                     let ptr = u32::from_ne_bytes(args.nth_checked::<i32>(0)?.to_ne_bytes());
                     let mut end = ptr;
@@ -557,7 +867,7 @@ impl Externals for HostFuncs {
                     } {
                         end += 1;
                     }
-                    match self.memory.get(ptr, (end - ptr) as usize) {
+                    match self.read_bytes(ptr, (end - ptr) as usize) {
                         Ok(v) => {
                             match String::from_utf8(v) {
                                 Ok(s) => Upcall::PostString(s),
@@ -588,19 +898,32 @@ impl Externals for HostFuncs {
                 UpcallId::Yield => Upcall::None,
             })))),
             HostCall::Constant(c) => Ok(Some(c.runtime_value(&self.config))),
-            HostCall::UnaryOpF32(op) | HostCall::UnaryOpF64(op) => {
-                Ok(Some(op.do_runtime(args.nth_value_checked(0)?)))
-            }
-            HostCall::BinaryOpF32(op) | HostCall::BinaryOpF64(op) => Ok(Some(
-                op.do_runtime(args.nth_value_checked(0)?, args.nth_value_checked(1)?),
-            )),
+            HostCall::UnaryOpF32(op) | HostCall::UnaryOpF64(op) => op
+                .do_runtime(args.nth_value_checked(0)?)
+                .map(Some)
+                .ok_or(Trap::new(TrapKind::UnexpectedSignature)),
+            HostCall::BinaryOpF32(op) | HostCall::BinaryOpF64(op) => op
+                .do_runtime(args.nth_value_checked(0)?, args.nth_value_checked(1)?)
+                .map(Some)
+                .ok_or(Trap::new(TrapKind::UnexpectedSignature)),
         }
     }
 }
 
+// Why a call to VM::run_until returned control to the host.
+#[derive(Debug)]
+pub enum StepResult {
+    Upcall(Upcall),
+    Exhausted, // Ran out of instructions for this step
+    Finished, // The entry point returned
+    Trapped(TrapKind),
+    Unanswered, // The host never gave the last upcall its result, so the program can't resume
+}
+
 pub struct VM {
     wasm_func: Box<FuncInvocation<'static>>,
     externals: HostFuncs,
+    instance: ModuleRef,
     state: VMState,
 }
 
@@ -611,38 +934,69 @@ enum VMState {
 }
 
 impl VM {
-    pub fn new(program: Vec<u8>, config: Configuration) -> Result<Self, wasmi::Error> {
+    // Host calls are resolved from imports under `namespace` (usually "env").
+    pub fn new(program: Vec<u8>, config: Arc<Configuration>, namespace: &str) -> Result<Self, wasmi::Error> {
         let imports = HostImports { };
         let module = wasmi::Module::from_buffer(&program)?;
         let instance = ModuleInstance::new(
             &module,
-            &ImportsBuilder::new().with_resolver("env", &imports),
+            &ImportsBuilder::new().with_resolver(namespace, &imports),
         )?;
-        let memory = instance.not_started_instance().export_by_name("memory")
+        // A start function would run unmetered, before the tank is ever stepped
+        if instance.has_start() {
+            return Err(wasmi::Error::Instantiation(
+                "Modules with a start function are not allowed; do setup in `tank`".into(),
+            ));
+        }
+        let instance = instance.not_started_instance().clone();
+        let memory = instance.export_by_name("memory")
             .ok_or(wasmi::Error::Instantiation("Could not access module memory; is it named `memory`?".into()))?
             .as_memory()
             .ok_or(wasmi::Error::Instantiation("Export `memory` is not a memory!".into()))?
             .clone();
+        // Memory can grow up to its declared maximum at any time, so with a cap that maximum
+        // must be declared (e.g. clang's -Wl,--max-memory) and within it.
+        if let Some(cap) = config.max_wasm_memory_pages {
+            match memory.maximum() {
+                Some(max) if max.0 <= cap => (),
+                Some(max) => return Err(wasmi::Error::Instantiation(format!(
+                    "Module memory may grow to {} pages, but at most {} are allowed",
+                    max.0, cap
+                ))),
+                None => return Err(wasmi::Error::Instantiation(format!(
+                    "Module memory must declare a maximum size of at most {} pages",
+                    cap
+                ))),
+            }
+        }
+        let init_instrs = clamp_count(config.init_instrs);
         let mut externals = HostFuncs {
-            memory, config,
+            memory, config, stats: None,
         };
-        if let Some(ExternVal::Func(fr)) = instance.not_started_instance().export_by_name(&"tank") {
+        if let Some(ExternVal::Func(fr)) = instance.export_by_name(&"tank") {
             let mut invocation = Box::new(FuncInstance::invoke_resumable(&fr, vec![])?);
-            let result = invocation.start_execution_until(&mut externals, Some(0));
-            loop {
-                // Not a real loop, just something we can break out of
-                if let Err(ResumableError::Trap(t)) = result {
-                    if let TrapKind::TooManyInstructions = t.kind() {
-                        break;
-                    }
-                }
-                return Err(wasmi::Error::Instantiation(
-                        "Invocation of WebAssembly failed before any steps were executed".into(),
-                        ))
+            // The tank may use up to `init_instrs` instructions before its first step, but has to
+            // still be running when they're gone.
+            let result = invocation.start_execution_until(&mut externals, Some(init_instrs));
+            let err = match result {
+                Err(ResumableError::Trap(t)) => match t.into_kind() {
+                    TrapKind::TooManyInstructions => None,
+                    TrapKind::Host(h) => Some(match h.downcast_ref::<Upcall>() {
+                        Some(uc) => format!("Entry point `tank` tried to {} before the first step", uc),
+                        None => "Entry point `tank` made a host call before the first step".into(),
+                    }),
+                    trap => Some(format!("Entry point `tank` trapped before the first step: {:?}", trap)),
+                },
+                Ok(_) => Some("Entry point `tank` returned before the first step".into()),
+                Err(e) => Some(format!("Entry point `tank` could not be started: {:?}", e)),
+            };
+            if let Some(err) = err {
+                return Err(wasmi::Error::Instantiation(err));
             }
             Ok(VM {
                 wasm_func: invocation,
                 externals,
+                instance,
                 state: VMState::Ready,
             })
         } else {
@@ -652,44 +1006,143 @@ impl VM {
         }
     }
 
+    // Read an exported global, e.g. a state flag a tank publishes for inspection.
+    pub fn read_global(&self, name: &str) -> Option<RuntimeValue> {
+        self.instance
+            .export_by_name(name)
+            .and_then(|e| e.as_global().map(|g| g.get()))
+    }
+
+    pub fn memory(&self) -> &MemoryRef {
+        &self.externals.memory
+    }
+
+    pub fn read_bytes(&self, ptr: u32, len: usize) -> Result<Vec<u8>, Trap> {
+        self.externals.read_bytes(ptr, len)
+    }
+
+    pub fn write_bytes(&self, ptr: u32, data: &[u8]) -> Result<(), Trap> {
+        self.externals.write_bytes(ptr, data)
+    }
+
+    // Tally every host call this VM makes from now on into `stats`, which may be shared.
+    pub fn set_call_stats(&mut self, stats: Arc<Mutex<CallStats>>) {
+        self.externals.stats = Some(stats);
+    }
+
     pub fn begin_step(&mut self) {
         self.wasm_func.reset_counter();
     }
 
+    // Instructions run this step. Never negative, so it's always safe to use as a usize.
     pub fn counter(&self) -> isize {
-        self.wasm_func.counter()
+        self.wasm_func.counter().max(0)
     }
 
+    // Saturates rather than wrapping, and stops at 0.
     pub fn add_counter(&mut self, addend: isize) {
-        self.wasm_func.add_counter(addend);
+        let counter = self.counter().saturating_add(addend);
+        self.set_counter(counter);
     }
 
     pub fn set_counter(&mut self, counter: isize) {
-        self.wasm_func.set_counter(counter);
+        self.wasm_func.set_counter(counter.max(0));
+    }
+
+    pub fn run_until(&mut self, max_count: Option<isize>) -> StepResult {
+        let val = match self.resume_value() {
+            Some(val) => val,
+            None => return StepResult::Unanswered,
+        };
+        //println!("running VM. state: {:?}. returned value: {:?}. expected value type: {:?}.", self.state, val, self.wasm_func.resumable_value_type());
+        self.state = VMState::Ready;
+        let result = self
+            .wasm_func
+            .resume_execution_until(val, &mut self.externals, max_count);
+        match result {
+            Err(ResumableError::Trap(t)) => match t.into_kind() {
+                TrapKind::TooManyInstructions => StepResult::Exhausted,
+                TrapKind::Host(h) => {
+                    let uc = h.downcast_ref::<Upcall>().cloned();
+                    match uc {
+                        Some(uc) => {
+                            self.state = VMState::Waiting(uc.clone());
+                            StepResult::Upcall(uc)
+                        }
+                        // Only upcalls are raised as host traps, but just in case
+                        None => StepResult::Trapped(TrapKind::Host(h)),
+                    }
+                }
+                trap => StepResult::Trapped(trap),
+            },
+            Ok(_) => StepResult::Finished,
+            // The only other failure is resuming an invocation that can't be resumed, i.e. one
+            // that has already run to completion.
+            Err(_) => StepResult::Finished,
+        }
     }
 
-    pub fn run_until(&mut self, max_count: Option<isize>) -> Upcall {
-        let val = match &self.state {
+    // What the pending upcall returns to the program, if anything; None if it should have
+    // returned something but the host never filled it in.
+    fn resume_value(&self) -> Option<Option<RuntimeValue>> {
+        Some(match &self.state {
             VMState::Ready => None,
             VMState::Waiting(Upcall::None) => None,
             VMState::Waiting(Upcall::Scan(_, _, v)) => Some(RuntimeValue::I64(i64::from_ne_bytes(
-                (*v.lock().unwrap())
-                    .expect("No value was returned by upcall")
+                (*v.lock().unwrap())?
                     .to_ne_bytes(),
             ))),
-            VMState::Waiting(Upcall::Fire) => None,
+            VMState::Waiting(Upcall::ScanInto(_, _, _)) => None,
+            VMState::Waiting(Upcall::ScanBullets(_, _, v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::ScanHottest(_, _, v)) => Some(RuntimeValue::F32(F32::from_float(
+                (*v.lock().unwrap())?,
+            ))),
+            VMState::Waiting(Upcall::Fire(v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::FireKind(_, v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
+            )),
             VMState::Waiting(Upcall::Aim(_)) => None,
             VMState::Waiting(Upcall::Turn(_)) => None,
             VMState::Waiting(Upcall::GPSX(v)) => Some(RuntimeValue::F32(F32::from_float(
-                v.lock().unwrap().expect("No value was returned by upcall"),
+                (*v.lock().unwrap())?,
             ))),
             VMState::Waiting(Upcall::GPSY(v)) => Some(RuntimeValue::F32(F32::from_float(
-                v.lock().unwrap().expect("No value was returned by upcall"),
+                (*v.lock().unwrap())?,
             ))),
             VMState::Waiting(Upcall::Temp(v)) => Some(RuntimeValue::I32(
-                v.lock().unwrap().expect("No value was returned by upcall"),
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::CooldownRemaining(v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
             )),
+            VMState::Waiting(Upcall::WasScanned(v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::MyTeam(v)) => Some(RuntimeValue::I32(
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::MyId(v)) => Some(RuntimeValue::I64(
+                (*v.lock().unwrap())?,
+            )),
+            VMState::Waiting(Upcall::TeamCentroidX(v)) => Some(RuntimeValue::F32(F32::from_float(
+                (*v.lock().unwrap())?,
+            ))),
+            VMState::Waiting(Upcall::TeamCentroidY(v)) => Some(RuntimeValue::F32(F32::from_float(
+                (*v.lock().unwrap())?,
+            ))),
+            VMState::Waiting(Upcall::BearingToTeam(_, v)) => Some(RuntimeValue::F32(F32::from_float(
+                (*v.lock().unwrap())?,
+            ))),
+            VMState::Waiting(Upcall::DistToTeam(_, v)) => Some(RuntimeValue::F32(F32::from_float(
+                (*v.lock().unwrap())?,
+            ))),
             VMState::Waiting(Upcall::Forward) => None,
+            VMState::Waiting(Upcall::SetThrottle(_)) => None,
+            VMState::Waiting(Upcall::SetLabel(_)) => None,
             VMState::Waiting(Upcall::PostString(_)) => None,
             VMState::Waiting(Upcall::PostI32(_)) => None,
             VMState::Waiting(Upcall::PostU32(_)) => None,
@@ -698,33 +1151,16 @@ impl VM {
             VMState::Waiting(Upcall::PostF32(_)) => None,
             VMState::Waiting(Upcall::PostF64(_)) => None,
             VMState::Waiting(Upcall::Explode) => None,
-        };
-        //println!("running VM. state: {:?}. returned value: {:?}. expected value type: {:?}.", self.state, val, self.wasm_func.resumable_value_type());
-        self.state = VMState::Ready;
-        let result = self
-            .wasm_func
-            .resume_execution_until(val, &mut self.externals, max_count);
-        match result {
-            Err(ResumableError::Trap(t)) => match t.kind() {
-                TrapKind::TooManyInstructions => Upcall::None,
-                TrapKind::Host(h) => {
-                    let uc = h.downcast_ref::<Upcall>().unwrap().clone();
-                    self.state = VMState::Waiting(uc.clone());
-                    uc
-                }
-                trap => {
-                    println!("tank trapped: {:?}", trap);
-                    Upcall::Explode
-                }
-            },
-            err => {
-                println!("tank finished or had an error: {:?}", err);
-                Upcall::Explode
-            }
-        }
+        })
     }
 }
 
+// Instruction counts are configured as usize, but the interpreter counts in isize; anything that
+// doesn't fit is as good as unlimited.
+pub fn clamp_count(count: usize) -> isize {
+    count.min(isize::max_value() as usize) as isize
+}
+
 // TODO: remove derive(Clone)s that necessitate this.
 impl Clone for VM {
     fn clone(&self) -> Self {
@@ -738,3 +1174,585 @@ impl core::fmt::Debug for VM {
         Ok(())
     }
 }
+
+// Assembles tiny wasm modules for tests, so programs can be written inline without a toolchain.
+// Every module exports its memory and a single function, `tank`, which takes and returns nothing;
+// host calls are imported by name and called by their position among the imports.
+#[cfg(test)]
+pub mod testprog {
+    use wasmi::ValueType;
+
+    use super::HostCall;
+
+    pub const UNREACHABLE: u8 = 0x00;
+    pub const NOP: u8 = 0x01;
+    pub const RETURN: u8 = 0x0f;
+    pub const DROP: u8 = 0x1a;
+    pub const F32_GT: u8 = 0x5e;
+
+    pub struct Module {
+        namespace: String,
+        imports: Vec<String>,
+        globals: Vec<(String, i32)>,
+        max_pages: Option<u32>,
+        start: bool,
+        body: Vec<u8>,
+    }
+
+    impl Module {
+        pub fn new(body: Vec<u8>) -> Module {
+            Module {
+                namespace: "env".into(),
+                imports: Vec::new(),
+                globals: Vec::new(),
+                max_pages: None,
+                start: false,
+                body,
+            }
+        }
+
+        // Names that aren't host calls are imported as taking and returning nothing.
+        pub fn with_imports(self, imports: &[&str]) -> Module {
+            Module {
+                imports: imports.iter().map(|&s| s.into()).collect(),
+                ..self
+            }
+        }
+
+        pub fn with_namespace(self, namespace: &str) -> Module {
+            Module {
+                namespace: namespace.into(),
+                ..self
+            }
+        }
+
+        // An exported (immutable) i32 global.
+        pub fn with_global(mut self, name: &str, value: i32) -> Module {
+            self.globals.push((name.into(), value));
+            self
+        }
+
+        pub fn with_max_pages(self, pages: u32) -> Module {
+            Module {
+                max_pages: Some(pages),
+                ..self
+            }
+        }
+
+        // Makes `tank` the start function as well.
+        pub fn with_start(self) -> Module {
+            Module { start: true, ..self }
+        }
+
+        pub fn build(&self) -> Vec<u8> {
+            let tank = self.imports.len() as u32;
+            let mut out = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+            let mut types = uleb(tank + 1);
+            for name in &self.imports {
+                let (params, ret) = HostCall::from_name(name)
+                    .map(|c| c.signature())
+                    .unwrap_or((vec![], None));
+                types.push(0x60);
+                types.extend(uleb(params.len() as u32));
+                types.extend(params.into_iter().map(value_type));
+                types.extend(uleb(ret.is_some() as u32));
+                types.extend(ret.into_iter().map(value_type));
+            }
+            types.extend(&[0x60, 0x00, 0x00]);
+            section(&mut out, 1, types);
+
+            let mut imports = uleb(tank);
+            for (i, name) in self.imports.iter().enumerate() {
+                imports.extend(name_bytes(&self.namespace));
+                imports.extend(name_bytes(name));
+                imports.push(0x00);
+                imports.extend(uleb(i as u32));
+            }
+            section(&mut out, 2, imports);
+
+            let mut functions = uleb(1);
+            functions.extend(uleb(tank));
+            section(&mut out, 3, functions);
+
+            let mut memory = uleb(1);
+            match self.max_pages {
+                Some(max) => {
+                    memory.extend(&[0x01, 0x01]);
+                    memory.extend(uleb(max));
+                }
+                None => memory.extend(&[0x00, 0x01]),
+            }
+            section(&mut out, 5, memory);
+
+            if !self.globals.is_empty() {
+                let mut globals = uleb(self.globals.len() as u32);
+                for &(_, value) in &self.globals {
+                    globals.extend(&[0x7f, 0x00]);
+                    globals.extend(i32_const(value));
+                    globals.push(0x0b);
+                }
+                section(&mut out, 6, globals);
+            }
+
+            let mut exports = uleb(2 + self.globals.len() as u32);
+            exports.extend(name_bytes("tank"));
+            exports.push(0x00);
+            exports.extend(uleb(tank));
+            exports.extend(name_bytes("memory"));
+            exports.extend(&[0x02, 0x00]);
+            for (i, (name, _)) in self.globals.iter().enumerate() {
+                exports.extend(name_bytes(name));
+                exports.push(0x03);
+                exports.extend(uleb(i as u32));
+            }
+            section(&mut out, 7, exports);
+
+            if self.start {
+                section(&mut out, 8, uleb(tank));
+            }
+
+            let mut body = vec![0x00]; // No locals
+            body.extend(&self.body);
+            body.push(0x0b);
+            let mut code = uleb(1);
+            code.extend(uleb(body.len() as u32));
+            code.extend(body);
+            section(&mut out, 10, code);
+            out
+        }
+    }
+
+    // Call the import at `index`.
+    pub fn call(index: u32) -> Vec<u8> {
+        let mut out = vec![0x10];
+        out.extend(uleb(index));
+        out
+    }
+
+    pub fn i32_const(value: i32) -> Vec<u8> {
+        let mut out = vec![0x41];
+        out.extend(sleb(value as i64));
+        out
+    }
+
+    pub fn i64_const(value: i64) -> Vec<u8> {
+        let mut out = vec![0x42];
+        out.extend(sleb(value));
+        out
+    }
+
+    pub fn f32_const(value: f32) -> Vec<u8> {
+        let mut out = vec![0x43];
+        out.extend(&value.to_bits().to_le_bytes());
+        out
+    }
+
+    // `body` over and over, until the host stops it.
+    pub fn forever(body: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x03, 0x40];
+        out.extend(body);
+        out.extend(&[0x0c, 0x00, 0x0b]);
+        out
+    }
+
+    // Run `body` only if the i32 on top of the stack is nonzero.
+    pub fn if_then(body: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x04, 0x40];
+        out.extend(body);
+        out.push(0x0b);
+        out
+    }
+
+    fn value_type(ty: ValueType) -> u8 {
+        match ty {
+            ValueType::I32 => 0x7f,
+            ValueType::I64 => 0x7e,
+            ValueType::F32 => 0x7d,
+            ValueType::F64 => 0x7c,
+        }
+    }
+
+    fn section(out: &mut Vec<u8>, id: u8, contents: Vec<u8>) {
+        out.push(id);
+        out.extend(uleb(contents.len() as u32));
+        out.extend(contents);
+    }
+
+    fn name_bytes(name: &str) -> Vec<u8> {
+        let mut out = uleb(name.len() as u32);
+        out.extend(name.as_bytes());
+        out
+    }
+
+    fn uleb(mut value: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                return out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn sleb(mut value: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+            if done {
+                out.push(byte);
+                return out;
+            }
+            out.push(byte | 0x80);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testprog::*;
+    use super::*;
+    use space::{Pair, AABB};
+
+    fn load_with(module: Module, config: Configuration) -> Result<VM, wasmi::Error> {
+        VM::new(module.build(), Arc::new(config), "env")
+    }
+
+    fn load(module: Module) -> VM {
+        load_with(module, Configuration::default()).unwrap()
+    }
+
+    #[test]
+    fn run_until_says_why_it_stopped() {
+        let mut spinner = load(Module::new(forever(&[NOP])));
+        match spinner.run_until(Some(100)) {
+            StepResult::Exhausted => (),
+            other => panic!("spinner: {:?}", other),
+        }
+        let mut yielder = load(Module::new(forever(&call(0))).with_imports(&["yield"]));
+        match yielder.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::None) => (),
+            other => panic!("yielder: {:?}", other),
+        }
+        let mut quitter = load(Module::new(vec![NOP, NOP, NOP]));
+        match quitter.run_until(Some(100)) {
+            StepResult::Finished => (),
+            other => panic!("quitter: {:?}", other),
+        }
+        let mut crasher = load(Module::new(vec![NOP, UNREACHABLE]));
+        match crasher.run_until(Some(100)) {
+            StepResult::Trapped(TrapKind::Unreachable) => (),
+            other => panic!("crasher: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unanswered_upcalls_are_reported() {
+        let mut body = call(0);
+        body.push(DROP);
+        let mut vm = load(Module::new(forever(&body)).with_imports(&["fire"]));
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::Fire(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match vm.run_until(Some(100)) {
+            StepResult::Unanswered => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn upcalls_share_one_budget_per_step() {
+        let mut body = f32_const(0.0);
+        body.extend(call(0));
+        let mut vm = load(Module::new(forever(&body)).with_imports(&["aim"]));
+        let mut upcalls = 0;
+        loop {
+            match vm.run_until(Some(20)) {
+                StepResult::Upcall(Upcall::Aim(_)) => upcalls += 1,
+                StepResult::Exhausted => break,
+                other => panic!("{:?}", other),
+            }
+            assert!(upcalls <= 20, "the budget started over after an upcall");
+        }
+        assert!(upcalls > 0);
+        vm.begin_step();
+        assert_eq!(vm.counter(), 0);
+        match vm.run_until(Some(20)) {
+            StepResult::Upcall(Upcall::Aim(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn exported_globals_can_be_read() {
+        let vm = load(Module::new(forever(&[NOP])).with_global("mode", 7));
+        match vm.read_global("mode") {
+            Some(RuntimeValue::I32(7)) => (),
+            other => panic!("{:?}", other),
+        }
+        assert!(vm.read_global("missing").is_none());
+        // Exported, but not a global
+        assert!(vm.read_global("tank").is_none());
+    }
+
+    #[test]
+    fn host_calls_come_from_the_configured_namespace() {
+        let module = Module::new(forever(&call(0))).with_imports(&["yield"]).with_namespace("ranks");
+        let config = Arc::new(Configuration::default());
+        let mut vm = VM::new(module.build(), config.clone(), "ranks").unwrap();
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::None) => (),
+            other => panic!("{:?}", other),
+        }
+        assert!(VM::new(module.build(), config, "env").is_err());
+    }
+
+    #[test]
+    fn unknown_host_calls_are_named() {
+        match HostCall::from_name("warp_drive") {
+            Err(HostCallError::UnknownName(name)) => assert_eq!(name, "warp_drive"),
+            Ok(call) => panic!("resolved to {:?}", call),
+        }
+        assert!(HostCall::from_name("fire").is_ok());
+        let err = load_with(
+            Module::new(forever(&call(0))).with_imports(&["warp_drive"]),
+            Configuration::default(),
+        )
+        .unwrap_err();
+        assert!(format!("{}", err).contains("warp_drive"), "{}", err);
+    }
+
+    fn init_error(module: Module) -> String {
+        let mut config = Configuration::default();
+        config.init_instrs = 100;
+        match load_with(module, config) {
+            Ok(_) => panic!("instantiated anyway"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn setup_is_metered_and_failures_explained() {
+        let mut config = Configuration::default();
+        config.init_instrs = 100;
+        assert!(load_with(Module::new(forever(&[NOP])), config).is_ok());
+        assert!(init_error(Module::new(vec![NOP])).contains("returned before the first step"));
+        assert!(init_error(Module::new(vec![NOP, UNREACHABLE])).contains("trapped before the first step"));
+        let upcall = init_error(Module::new(forever(&call(0))).with_imports(&["fire"]));
+        assert!(upcall.contains("tried to fire"), "{}", upcall);
+        assert!(init_error(Module::new(forever(&[NOP])).with_start()).contains("start function"));
+    }
+
+    #[test]
+    fn tanks_can_look_up_the_arena_bounds() {
+        let bound = AABB::from_corners(Pair { x: -100.0, y: -50.0 }, Pair { x: 300.0, y: 250.0 });
+        let config = Configuration {
+            world_bound: bound.clone(),
+            ..Configuration::default()
+        };
+        let names = ["ARENA_MIN_X", "ARENA_MIN_Y", "ARENA_MAX_X", "ARENA_MAX_Y", "post_float"];
+        let mut body = Vec::new();
+        for i in 0..4 {
+            body.extend(call(i));
+            body.extend(call(4));
+        }
+        let mut vm = load_with(Module::new(body).with_imports(&names), config.clone()).unwrap();
+        for &expected in &[-100.0, -50.0, 300.0, 250.0] {
+            match vm.run_until(Some(100)) {
+                StepResult::Upcall(Upcall::PostF32(v)) => assert_eq!(v, expected),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(config.build().arena_bounds(), bound);
+    }
+
+    #[test]
+    fn memory_accessors_are_bounds_checked() {
+        let vm = load(Module::new(vec![NOP]));
+        vm.write_bytes(100, b"tank").unwrap();
+        assert_eq!(vm.read_bytes(100, 4).unwrap(), b"tank".to_vec());
+        assert_eq!(vm.memory().get(100, 4).unwrap(), b"tank".to_vec());
+        // The module has a single 64KiB page
+        assert!(vm.read_bytes(65532, 4).is_ok());
+        for result in vec![vm.read_bytes(65533, 4).map(|_| ()), vm.write_bytes(65535, b"xy")] {
+            match result {
+                Err(ref trap) => match *trap.kind() {
+                    TrapKind::MemoryAccessOutOfBounds => (),
+                    ref other => panic!("{:?}", other),
+                },
+                Ok(()) => panic!("out of bounds access succeeded"),
+            }
+        }
+    }
+
+    #[test]
+    fn memory_must_stay_under_the_cap() {
+        let capped = Configuration {
+            max_wasm_memory_pages: Some(4),
+            ..Configuration::default()
+        };
+        let module = |max: Option<u32>| match max {
+            Some(max) => Module::new(vec![NOP]).with_max_pages(max),
+            None => Module::new(vec![NOP]),
+        };
+        assert!(load_with(module(Some(4)), capped.clone()).is_ok());
+        assert!(load_with(module(None), Configuration::default()).is_ok());
+        for max in vec![None, Some(8)] {
+            match load_with(module(max), capped.clone()) {
+                Err(wasmi::Error::Instantiation(msg)) => assert!(msg.contains("at most 4"), "{}", msg),
+                Err(e) => panic!("{:?}", e),
+                Ok(_) => panic!("{:?} pages got past a cap of 4", max),
+            }
+        }
+    }
+
+    #[test]
+    fn answers_reach_the_program() {
+        let body = [call(0), call(1), call(2), call(3)].concat();
+        let imports = ["my_id", "post_int64", "my_team", "post_int32"];
+        let mut vm = load(Module::new(body).with_imports(&imports));
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::MyId(rv)) => *rv.lock().unwrap() = Some(1 << 40),
+            other => panic!("{:?}", other),
+        }
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::PostI64(v)) => assert_eq!(v, 1 << 40),
+            other => panic!("{:?}", other),
+        }
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::MyTeam(rv)) => *rv.lock().unwrap() = Some(300),
+            other => panic!("{:?}", other),
+        }
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::PostI32(v)) => assert_eq!(v, 300),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn host_calls_are_tallied_by_kind() {
+        let stats = Arc::new(Mutex::new(CallStats::default()));
+        let body = [f32_const(-1.0), call(0), vec![DROP], call(1), vec![DROP], call(2)].concat();
+        let imports = ["abs_float", "TANK_VELOCITY", "yield"];
+        for _ in 0..2 {
+            let mut vm = load(Module::new(body.clone()).with_imports(&imports));
+            vm.set_call_stats(stats.clone());
+            match vm.run_until(Some(100)) {
+                StepResult::Upcall(Upcall::None) => (),
+                other => panic!("{:?}", other),
+            }
+        }
+        let stats = stats.lock().unwrap().clone();
+        assert_eq!(
+            stats,
+            CallStats {
+                upcalls: 2,
+                constants: 2,
+                unary_f32: 2,
+                ..CallStats::default()
+            }
+        );
+        assert_eq!(stats.math(), 2);
+    }
+
+    #[test]
+    fn shoot_heat_includes_the_speed_term() {
+        let config = Configuration {
+            heat_per_bullet_speed: 2.0,
+            ..Configuration::default()
+        };
+        let body = [call(0), call(1)].concat();
+        let module = Module::new(body).with_imports(&["SHOOT_HEAT", "post_int32"]);
+        let mut vm = load_with(module, config).unwrap();
+        match vm.run_until(Some(100)) {
+            StepResult::Upcall(Upcall::PostI32(heat)) => assert_eq!(heat, 26 + 10),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn labels_are_read_from_memory_and_cut_short() {
+        let label = |ptr: i32, len: i32| [i32_const(ptr), i32_const(len), call(0)].concat();
+        let body = [label(0, 5), label(16, 7), label(32, 2), label(65534, 4)].concat();
+        let config = Configuration {
+            max_label_len: 6,
+            ..Configuration::default()
+        };
+        let mut vm = load_with(Module::new(body).with_imports(&["set_label"]), config).unwrap();
+        vm.write_bytes(0, b"hello").unwrap();
+        vm.write_bytes(16, "aaaaa\u{f1}".as_bytes()).unwrap();
+        vm.write_bytes(32, &[0xff, 0x41]).unwrap();
+        let mut next = || match vm.run_until(Some(100)) {
+            StepResult::Upcall(uc) => uc,
+            other => panic!("{:?}", other),
+        };
+        match next() {
+            Upcall::SetLabel(s) => assert_eq!(s, "hello"),
+            other => panic!("{:?}", other),
+        }
+        // The cut at 6 bytes splits the last character, which goes too
+        match next() {
+            Upcall::SetLabel(s) => assert_eq!(s, "aaaaa"),
+            other => panic!("{:?}", other),
+        }
+        // Text that isn't UTF-8, or isn't there at all, is a fault
+        for _ in 0..2 {
+            match next() {
+                Upcall::Explode => (),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn instruction_counters_saturate_and_stay_non_negative() {
+        assert_eq!(clamp_count(30), 30);
+        assert_eq!(clamp_count(usize::max_value()), isize::max_value());
+        let mut vm = load(Module::new(vec![NOP]));
+        vm.set_counter(10);
+        vm.add_counter(-25);
+        assert_eq!(vm.counter(), 0);
+        vm.set_counter(-5);
+        assert_eq!(vm.counter(), 0);
+        vm.set_counter(isize::max_value() - 1);
+        vm.add_counter(10);
+        assert_eq!(vm.counter(), isize::max_value());
+        // A setup budget too big for the interpreter is as good as unlimited, rather than wrapping
+        // round to nothing: the program gets as far as its host call
+        let config = Configuration {
+            init_instrs: usize::max_value(),
+            ..Configuration::default()
+        };
+        let module = Module::new(forever(&call(0))).with_imports(&["fire"]);
+        match load_with(module, config) {
+            Ok(_) => panic!("setup was cut short"),
+            Err(e) => assert!(e.to_string().contains("tried to fire"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn malformed_host_calls_are_refused_rather_than_panicking() {
+        for id in 0..B6 {
+            assert_eq!(HostCall::from_id(id).map(|c| c.to_id()), Some(id));
+        }
+        assert!(HostCall::from_id(B6).is_none());
+        assert!(HostCall::from_id(usize::max_value()).is_none());
+        match UnaryOp::Abs.do_runtime(RuntimeValue::F32(F32::from_float(-2.0))) {
+            Some(RuntimeValue::F32(x)) => assert_eq!(x.to_float(), 2.0),
+            other => panic!("{:?}", other),
+        }
+        assert!(UnaryOp::Abs.do_runtime(RuntimeValue::I32(-2)).is_none());
+        let (a, b) = (RuntimeValue::F32(F32::from_float(1.0)), RuntimeValue::F64(F64::from_float(2.0)));
+        assert!(BinaryOp::Max.do_runtime(a, b).is_none());
+        match BinaryOp::Max.do_runtime(b, b) {
+            Some(RuntimeValue::F64(x)) => assert_eq!(x.to_float(), 2.0),
+            other => panic!("{:?}", other),
+        }
+    }
+}
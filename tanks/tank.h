@@ -13,6 +13,11 @@ extern float BULLET_SPACING();
 extern float TANK_HIT_RADIUS();
 extern float TANK_VELOCITY();
 extern float EXPLOSION_RADIUS();
+extern float BULLET_RADIUS();
+extern float ARENA_MIN_X();
+extern float ARENA_MIN_Y();
+extern float ARENA_MAX_X();
+extern float ARENA_MAX_Y();
 
 // See the Rust standard library for details on each of these.
 extern float abs_float(float);
@@ -95,14 +100,39 @@ extern double min_double(double, double);
 extern double powf_double(double, double);
 extern double rem_euclid_double(double, double);
 
+// Our tanks in the high 32 bits, theirs in the low
 extern uint64_t scan(float, float);
-extern void fire();
+// The same counts, written to out[0] (ours) and out[1] (theirs)
+extern void scan_into(float, float, uint32_t* out);
+extern int32_t scan_bullets(float, float);
+// Bearing to the hottest enemy between the two bearings, or -1 if there is none
+extern float scan_hottest(float, float);
+// Returns 0 if the shot went out, 1 on cooldown, 2 if too hot, 3 if at the bullet cap, 4 if
+// the gun jammed, 5 inside a no-fire zone
+extern int32_t fire();
+// As fire(), with the kind of bullet: 0 for standard, 1 for heavy, 2 for scatter. Anything else
+// fires a standard bullet.
+extern int32_t fire_kind(int32_t);
 extern void aim(float);
 extern void turn(float);
 extern float gpsx();
 extern float gpsy();
 extern int32_t temp();
+extern int32_t cooldown_remaining();
+// How many enemy scans caught this tank during the last step
+extern int32_t was_scanned();
+extern int32_t my_team();
+extern int64_t my_id();
+// Where the living tanks of this tank's team are on average; NaN if there are none
+extern float team_centroid_x();
+extern float team_centroid_y();
+// To the nearest living tank on the given team (other than this one); -1 if there is none
+extern float bearing_to_team(int32_t);
+extern float dist_to_team(int32_t);
 extern void forward();
+extern void set_throttle(float);
+// A name for spectators to see; UTF-8, not NUL-terminated, and cut short if too long
+extern void set_label(const char*, uint32_t);
 extern void explode();
 extern void post_string(char*);
 extern void post_int32(int32_t);